@@ -0,0 +1,20 @@
+use day_12_rust::nonogram::{render_grid, Nonogram};
+
+fn main() {
+    let input = match std::env::args().nth(1) {
+        Some(path) => std::fs::read_to_string(path).expect("failed to read puzzle"),
+        None => include_str!("nonogram.txt").to_owned(),
+    };
+
+    let nonogram = match Nonogram::parse(&input) {
+        Ok(nonogram) => nonogram,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            std::process::exit(1);
+        }
+    };
+    match nonogram.solve() {
+        Some(grid) => println!("{}", render_grid(&grid)),
+        None => println!("No solution"),
+    }
+}
@@ -1,12 +1,4 @@
-use nom::{
-    branch::alt,
-    bytes::complete::tag,
-    character::complete::{self, space1},
-    combinator::map,
-    multi::{many1, separated_list1},
-    sequence::separated_pair,
-    IResult,
-};
+use day_12_rust::{parse_record, render, Record, Status, TooManyArrangements};
 use rayon::prelude::*;
 
 #[derive(Debug)]
@@ -15,128 +7,106 @@ struct Field {
 }
 
 impl Field {
-    fn from_str(input: &str) -> Self {
+    fn from_str(input: &str, times: usize, separator: Status) -> Self {
         Self {
             springs: input
                 .lines()
                 .flat_map(parse_record)
-                .map(|(_, r)| r.unfold())
+                .map(|(_, r)| r.unfold(times, separator.clone()))
                 .collect(),
         }
     }
 
-    fn sum_of_valid_arrangements(self) -> u64 {
+    fn sum_of_valid_arrangements(self) -> Result<u128, TooManyArrangements> {
         self.springs
             .into_par_iter()
             .map(|r| r.valid_arrangements())
-            .sum()
+            .try_reduce(|| 0, |a, b| a.checked_add(b).ok_or(TooManyArrangements))
     }
 }
 
-#[derive(Debug)]
-struct Record {
-    statuses: Vec<Status>,
-    damaged_counts: Vec<usize>,
+enum Mode {
+    Count,
+    Sample,
+    Enumerate(usize),
 }
 
-impl Record {
-    fn unfold(self) -> Self {
-        let statuses = self.statuses.len();
-        let statuses = self
-            .statuses
-            .into_iter()
-            .chain([Status::Unknown])
-            .cycle()
-            .take(statuses * 5 + 4)
-            .collect();
+struct Args {
+    mode: Mode,
+    times: usize,
+    separator: Status,
+}
 
-        let damaged_counts = self.damaged_counts.len();
-        let damaged_counts = self
-            .damaged_counts
-            .into_iter()
-            .cycle()
-            .take(damaged_counts * 5)
-            .collect();
+fn parse_args() -> Args {
+    let mut args = Args {
+        mode: Mode::Count,
+        times: 5,
+        separator: Status::Unknown,
+    };
 
-        Self {
-            statuses,
-            damaged_counts,
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--sample" => args.mode = Mode::Sample,
+            "--enumerate" => {
+                let limit = iter.next().and_then(|n| n.parse().ok()).unwrap_or(10);
+                args.mode = Mode::Enumerate(limit);
+            }
+            "--unfold" => {
+                args.times = iter
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .expect("--unfold expects a repeat count");
+            }
+            "--separator" => {
+                args.separator = match iter.next().as_deref() {
+                    Some(".") => Status::Operational,
+                    Some("#") => Status::Damaged,
+                    Some("?") => Status::Unknown,
+                    _ => panic!("--separator expects one of . # ?"),
+                };
+            }
+            _ => panic!("unknown argument {arg}"),
         }
     }
 
-    fn valid_arrangements(mut self) -> u64 {
-        // to make the Damaged recursion case simpler
-        self.statuses.push(Status::Operational);
-        let mut cache = vec![vec![None; self.statuses.len()]; self.damaged_counts.len()];
-        count_possible_arangements_inner(&self.statuses, self.damaged_counts.as_slice(), &mut cache)
-    }
+    args
 }
 
-fn count_possible_arangements_inner(
-    statuses: &[Status],
-    counts: &[usize],
-    cache: &mut [Vec<Option<u64>>],
-) -> u64 {
-    if counts.is_empty() {
-        return if statuses.contains(&Status::Damaged) {
-            // Too many previous unknowns were counted as damaged
-            0
-        } else {
-            // All remaining unknowns are operational
-            1
-        };
-    }
-    if statuses.len() < counts.iter().sum::<usize>() + counts.len() {
-        // Not enough space for remaining numbers
-        return 0;
-    }
-    if let Some(cached) = cache[counts.len() - 1][statuses.len() - 1] {
-        return cached;
-    }
-    let mut arangements = 0;
-    if statuses[0] != Status::Damaged {
-        // Assume operational
-        arangements += count_possible_arangements_inner(&statuses[1..], counts, cache);
-    }
-    let next_group_size = counts[0];
-    if !statuses[..next_group_size].contains(&Status::Operational)
-        && statuses[next_group_size] != Status::Damaged
-    {
-        // Assume damaged
-        arangements +=
-            count_possible_arangements_inner(&statuses[next_group_size + 1..], &counts[1..], cache);
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("Error: {error}");
+        std::process::exit(1);
     }
-    cache[counts.len() - 1][statuses.len() - 1] = Some(arangements);
-    arangements
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
-enum Status {
-    Operational,
-    Damaged,
-    Unknown,
-}
+fn run() -> Result<(), TooManyArrangements> {
+    let args = parse_args();
+    let field = Field::from_str(include_str!("input.txt"), args.times, args.separator);
 
-fn parse_record(input: &str) -> IResult<&str, Record> {
-    map(
-        separated_pair(
-            many1(alt((
-                map(complete::char('.'), |_| Status::Operational),
-                map(complete::char('#'), |_| Status::Damaged),
-                map(complete::char('?'), |_| Status::Unknown),
-            ))),
-            space1,
-            separated_list1(tag(","), map(complete::u64, |n| n as usize)),
-        ),
-        |(statuses, damaged_spring_groups)| Record {
-            statuses,
-            damaged_counts: damaged_spring_groups,
-        },
-    )(input)
-}
+    match args.mode {
+        Mode::Sample => {
+            let mut rng = rand::thread_rng();
+            for record in field.springs.iter() {
+                match record.sample(&mut rng)? {
+                    Some(arrangement) => println!("{}", render(&arrangement)),
+                    None => println!("{} (no arrangements)", render(&record.statuses)),
+                }
+            }
+        }
+        Mode::Enumerate(limit) => {
+            for record in field.springs.iter() {
+                println!("{}", render(&record.statuses));
+                for arrangement in record.arrangements().take(limit) {
+                    println!("  {}", render(&arrangement));
+                }
+            }
+        }
+        Mode::Count => {
+            let arrangements = field.sum_of_valid_arrangements()?;
+            println!("Answer: {arrangements}");
+        }
+    }
 
-fn main() {
-    let field = Field::from_str(include_str!("input.txt"));
-    let arrangements = field.sum_of_valid_arrangements();
-    println!("Answer: {arrangements}");
+    Ok(())
 }
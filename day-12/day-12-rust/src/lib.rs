@@ -0,0 +1,516 @@
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{self, space1},
+    combinator::map,
+    multi::{many1, separated_list1},
+    sequence::separated_pair,
+    IResult,
+};
+use std::fmt;
+
+use rand::Rng;
+
+pub mod nonogram;
+
+/// A record with more valid arrangements than fit in a `u128`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TooManyArrangements;
+
+impl fmt::Display for TooManyArrangements {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "too many arrangements to count in a u128")
+    }
+}
+
+impl std::error::Error for TooManyArrangements {}
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub statuses: Vec<Status>,
+    pub damaged_counts: Vec<usize>,
+}
+
+impl Record {
+    /// Repeats the record `times` times, joining the copies of the statuses
+    /// with `separator`.
+    pub fn unfold(self, times: usize, separator: Status) -> Self {
+        let statuses = self.statuses.len();
+        let statuses = self
+            .statuses
+            .into_iter()
+            .chain([separator])
+            .cycle()
+            .take(statuses * times + times.saturating_sub(1))
+            .collect();
+
+        let damaged_counts = self.damaged_counts.len();
+        let damaged_counts = self
+            .damaged_counts
+            .into_iter()
+            .cycle()
+            .take(damaged_counts * times)
+            .collect();
+
+        Self {
+            statuses,
+            damaged_counts,
+        }
+    }
+
+    pub fn valid_arrangements(&self) -> Result<u128, TooManyArrangements> {
+        Table::new(self).ways(0, 0).ok_or(TooManyArrangements)
+    }
+
+    /// Whether the record has any valid arrangement, however many.
+    fn is_solvable(&self) -> bool {
+        Table::new(self).ways(0, 0) != Some(0)
+    }
+
+    /// Lazily yields every valid assignment of the unknown springs.
+    pub fn arrangements(&self) -> Arrangements {
+        Arrangements::new(self)
+    }
+
+    /// Picks one valid assignment uniformly at random, weighting every choice
+    /// by the number of arrangements that follow from it, or `None` when
+    /// there is no valid arrangement.
+    pub fn sample(&self, rng: &mut impl Rng) -> Result<Option<Vec<Status>>, TooManyArrangements> {
+        let table = Table::new(self);
+        let mut total = table.ways(0, 0).ok_or(TooManyArrangements)?;
+        if total == 0 {
+            return Ok(None);
+        }
+
+        let mut assigned = Vec::with_capacity(table.len() + 1);
+        let (mut i, mut j) = (0, 0);
+        while j < table.counts.len() {
+            let pick = rng.gen_range(0..total);
+            let operational = table.operational(i, j).ok_or(TooManyArrangements)?;
+            if pick < operational {
+                assigned.push(Status::Operational);
+                i += 1;
+                total = operational;
+            } else {
+                let size = table.counts[j];
+                assigned.extend(std::iter::repeat_n(Status::Damaged, size));
+                assigned.push(Status::Operational);
+                i = table.after(i, j);
+                j += 1;
+                total -= operational;
+            }
+        }
+
+        Ok(Some(table.finish(assigned)))
+    }
+
+    /// Fixes every unknown spring that has the same status in all valid
+    /// arrangements, or `None` when there is no valid arrangement at all.
+    pub fn line_solve(&self) -> Option<Vec<Status>> {
+        let fits = |index: usize, status: Status| {
+            let mut statuses = self.statuses.clone();
+            statuses[index] = status;
+            Record {
+                statuses,
+                damaged_counts: self.damaged_counts.clone(),
+            }
+            .is_solvable()
+        };
+
+        if !self.is_solvable() {
+            return None;
+        }
+
+        let solved = self
+            .statuses
+            .iter()
+            .enumerate()
+            .map(|(index, status)| match status {
+                Status::Unknown => match (
+                    fits(index, Status::Operational),
+                    fits(index, Status::Damaged),
+                ) {
+                    (true, false) => Status::Operational,
+                    (false, true) => Status::Damaged,
+                    _ => Status::Unknown,
+                },
+                known => known.clone(),
+            })
+            .collect();
+
+        Some(solved)
+    }
+}
+
+/// Bottom-up arrangement counts for one record.
+///
+/// `ways[j][i]` is the number of ways to place the groups `counts[j..]` in
+/// the springs `statuses[i..]`, for every `i` in `0..=len` and `j` in
+/// `0..=counts.len()`. The table upholds these invariants:
+///
+/// - `ways[counts.len()][i]` is 1 when `statuses[i..]` has no damaged
+///   spring left and 0 otherwise, since every remaining spring must be
+///   operational.
+/// - `ways[j][len]` is 0 for every `j < counts.len()`, as there is no room
+///   left for another group.
+/// - a group of `size` starting at `i` must not overlap an operational
+///   spring and must be followed by a spring that can be operational or by
+///   the end of the record, so the next group can start at
+///   `min(i + size + 1, len)` at the earliest.
+///
+/// Zero-length groups are dropped up front because they place no damaged
+/// springs. Counts are added with overflow checks, and a count of `None`
+/// stands for more than fit in a `u128`, which still means at least one.
+struct Table {
+    statuses: Vec<Status>,
+    counts: Vec<usize>,
+    ways: Vec<Vec<Option<u128>>>,
+}
+
+impl Table {
+    fn new(record: &Record) -> Self {
+        let statuses = record.statuses.clone();
+        let counts = record
+            .damaged_counts
+            .iter()
+            .copied()
+            .filter(|size| *size > 0)
+            .collect::<Vec<_>>();
+        let len = statuses.len();
+
+        let mut table = Self {
+            ways: vec![vec![Some(0); len + 1]; counts.len() + 1],
+            statuses,
+            counts,
+        };
+
+        // no groups left: valid only while no damaged spring remains
+        table.ways[table.counts.len()][len] = Some(1);
+        for i in (0..len).rev() {
+            if table.statuses[i] != Status::Damaged {
+                table.ways[table.counts.len()][i] = table.ways[table.counts.len()][i + 1];
+            }
+        }
+
+        for j in (0..table.counts.len()).rev() {
+            for i in (0..len).rev() {
+                table.ways[j][i] = table
+                    .operational(i, j)
+                    .zip(table.damaged(i, j))
+                    .and_then(|(operational, damaged)| operational.checked_add(damaged));
+            }
+        }
+
+        table
+    }
+
+    fn len(&self) -> usize {
+        self.statuses.len()
+    }
+
+    /// Arrangements of `statuses[i..]` using `counts[j..]`.
+    fn ways(&self, i: usize, j: usize) -> Option<u128> {
+        self.ways[j][i]
+    }
+
+    /// Arrangements where spring `i` is operational.
+    fn operational(&self, i: usize, j: usize) -> Option<u128> {
+        if i < self.len() && self.statuses[i] != Status::Damaged {
+            self.ways(i + 1, j)
+        } else {
+            Some(0)
+        }
+    }
+
+    /// Arrangements where group `j` starts at spring `i`.
+    fn damaged(&self, i: usize, j: usize) -> Option<u128> {
+        let size = self.counts[j];
+        let end = i + size;
+        if end <= self.len()
+            && !self.statuses[i..end].contains(&Status::Operational)
+            && self.statuses.get(end) != Some(&Status::Damaged)
+        {
+            self.ways(self.after(i, j), j + 1)
+        } else {
+            Some(0)
+        }
+    }
+
+    /// The first spring a group after group `j` could use if group `j`
+    /// starts at spring `i`.
+    fn after(&self, i: usize, j: usize) -> usize {
+        (i + self.counts[j] + 1).min(self.len())
+    }
+
+    /// Once every group is placed the remaining springs are operational.
+    fn finish(&self, mut assigned: Vec<Status>) -> Vec<Status> {
+        assigned.resize(self.len(), Status::Operational);
+        assigned.truncate(self.len());
+        assigned
+    }
+}
+
+pub struct Arrangements {
+    table: Table,
+    stack: Vec<(usize, usize, Vec<Status>)>,
+}
+
+impl Arrangements {
+    fn new(record: &Record) -> Self {
+        let table = Table::new(record);
+        let stack = if table.ways(0, 0) != Some(0) {
+            vec![(0, 0, Vec::with_capacity(table.len() + 1))]
+        } else {
+            vec![]
+        };
+        Self { table, stack }
+    }
+}
+
+impl Iterator for Arrangements {
+    type Item = Vec<Status>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((i, j, assigned)) = self.stack.pop() {
+            if j == self.table.counts.len() {
+                return Some(self.table.finish(assigned));
+            }
+
+            // pushed first so the damaged branch is explored first
+            if self.table.operational(i, j) != Some(0) {
+                let mut assigned = assigned.clone();
+                assigned.push(Status::Operational);
+                self.stack.push((i + 1, j, assigned));
+            }
+
+            if self.table.damaged(i, j) != Some(0) {
+                let size = self.table.counts[j];
+                let mut assigned = assigned;
+                assigned.extend(std::iter::repeat_n(Status::Damaged, size));
+                assigned.push(Status::Operational);
+                self.stack.push((self.table.after(i, j), j + 1, assigned));
+            }
+        }
+
+        None
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Status {
+    Operational,
+    Damaged,
+    Unknown,
+}
+
+pub fn render(statuses: &[Status]) -> String {
+    statuses
+        .iter()
+        .map(|status| match status {
+            Status::Operational => '.',
+            Status::Damaged => '#',
+            Status::Unknown => '?',
+        })
+        .collect()
+}
+
+pub fn parse_record(input: &str) -> IResult<&str, Record> {
+    map(
+        separated_pair(
+            many1(alt((
+                map(complete::char('.'), |_| Status::Operational),
+                map(complete::char('#'), |_| Status::Damaged),
+                map(complete::char('?'), |_| Status::Unknown),
+            ))),
+            space1,
+            separated_list1(tag(","), map(complete::u64, |n| n as usize)),
+        ),
+        |(statuses, damaged_spring_groups)| Record {
+            statuses,
+            damaged_counts: damaged_spring_groups,
+        },
+    )(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse_record, Record, Status, TooManyArrangements};
+    use itertools::Itertools;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn records() -> Vec<Record> {
+        include_str!("bin/test.txt")
+            .lines()
+            .flat_map(parse_record)
+            .map(|(_, r)| r)
+            .collect()
+    }
+
+    fn is_valid(record: &Record, arrangement: &[Status]) -> bool {
+        arrangement.len() == record.statuses.len()
+            && arrangement
+                .iter()
+                .zip(record.statuses.iter())
+                .all(|(a, s)| *s == Status::Unknown || a == s)
+            && arrangement
+                .iter()
+                .group_by(|status| *status)
+                .into_iter()
+                .filter(|(key, _)| **key == Status::Damaged)
+                .map(|(_, group)| group.count())
+                .eq(record.damaged_counts.iter().copied().filter(|n| *n > 0))
+    }
+
+    /// Tries every assignment of the unknown springs.
+    fn brute_force(record: &Record) -> u128 {
+        let unknowns = record
+            .statuses
+            .iter()
+            .positions(|status| *status == Status::Unknown)
+            .collect::<Vec<_>>();
+
+        (0..1u32 << unknowns.len())
+            .filter(|mask| {
+                let mut arrangement = record.statuses.clone();
+                for (bit, index) in unknowns.iter().enumerate() {
+                    arrangement[*index] = if mask & (1 << bit) != 0 {
+                        Status::Damaged
+                    } else {
+                        Status::Operational
+                    };
+                }
+                is_valid(record, &arrangement)
+            })
+            .count() as u128
+    }
+
+    fn random_record(rng: &mut impl Rng) -> Record {
+        let statuses = (0..rng.gen_range(0..=12))
+            .map(|_| match rng.gen_range(0..3) {
+                0 => Status::Operational,
+                1 => Status::Damaged,
+                _ => Status::Unknown,
+            })
+            .collect();
+        let damaged_counts = (0..rng.gen_range(0..=4))
+            .map(|_| rng.gen_range(0..=4))
+            .collect();
+
+        Record {
+            statuses,
+            damaged_counts,
+        }
+    }
+
+    #[test]
+    fn enumerates_every_arrangement_once() {
+        for record in records() {
+            let arrangements = record.arrangements().collect::<Vec<_>>();
+            assert!(arrangements.iter().all(|a| is_valid(&record, a)));
+            assert_eq!(arrangements.iter().unique().count(), arrangements.len());
+
+            let count = arrangements.len() as u128;
+            assert_eq!(Ok(count), record.valid_arrangements());
+        }
+    }
+
+    #[test]
+    fn samples_valid_arrangements() {
+        let mut rng = StdRng::seed_from_u64(26);
+        for record in records().into_iter().map(|r| r.unfold(5, Status::Unknown)) {
+            let arrangement = record.sample(&mut rng).unwrap().expect("no arrangement");
+            assert!(is_valid(&record, &arrangement));
+        }
+    }
+
+    #[test]
+    fn unfolds() {
+        let counts = records()
+            .into_iter()
+            .map(|r| r.unfold(5, Status::Unknown).valid_arrangements().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![1, 16384, 1, 16, 2500, 506250]);
+
+        let record = parse_record("???.### 1,1,3").unwrap().1;
+        let unfolded = record.unfold(3, Status::Operational);
+        assert_eq!(unfolded.statuses.len(), 3 * 7 + 2);
+        assert_eq!(unfolded.damaged_counts, vec![1, 1, 3, 1, 1, 3, 1, 1, 3]);
+        assert_eq!(unfolded.valid_arrangements(), Ok(1));
+
+        let record = parse_record("?###???????? 3,2,1").unwrap().1;
+        let unfolded = record.unfold(0, Status::Unknown);
+        assert!(unfolded.statuses.is_empty());
+        assert_eq!(unfolded.valid_arrangements(), Ok(1));
+    }
+
+    #[test]
+    fn large_unfolds_do_not_overflow() {
+        let record = parse_record("?###???????? 3,2,1").unwrap().1;
+        let count = record
+            .clone()
+            .unfold(20, Status::Unknown)
+            .valid_arrangements();
+        assert!(count.unwrap() > u64::MAX as u128);
+
+        let unfolded = record.unfold(60, Status::Unknown);
+        assert_eq!(unfolded.valid_arrangements(), Err(TooManyArrangements));
+        assert_eq!(
+            unfolded.sample(&mut StdRng::seed_from_u64(0)),
+            Err(TooManyArrangements)
+        );
+        assert!(unfolded.arrangements().next().is_some());
+    }
+
+    #[test]
+    fn fuzz_against_brute_force() {
+        let mut rng = StdRng::seed_from_u64(12);
+        for _ in 0..5000 {
+            let record = random_record(&mut rng);
+            let expected = brute_force(&record);
+
+            assert_eq!(record.valid_arrangements(), Ok(expected), "{record:?}");
+            assert_eq!(
+                record.arrangements().count() as u128,
+                expected,
+                "{record:?}"
+            );
+            assert!(record.arrangements().all(|a| is_valid(&record, &a)));
+
+            match record.sample(&mut rng).unwrap() {
+                Some(arrangement) => assert!(is_valid(&record, &arrangement)),
+                None => assert_eq!(expected, 0),
+            }
+        }
+    }
+
+    #[test]
+    fn edge_cases() {
+        let record = |statuses: Vec<Status>, damaged_counts: Vec<usize>| Record {
+            statuses,
+            damaged_counts,
+        };
+        use Status::*;
+
+        assert_eq!(record(vec![], vec![]).valid_arrangements(), Ok(1));
+        assert_eq!(record(vec![], vec![1]).valid_arrangements(), Ok(0));
+        assert_eq!(record(vec![], vec![0, 0]).valid_arrangements(), Ok(1));
+        assert_eq!(record(vec![Damaged], vec![]).valid_arrangements(), Ok(0));
+        assert_eq!(record(vec![Damaged], vec![1]).valid_arrangements(), Ok(1));
+        assert_eq!(
+            record(vec![Unknown], vec![0, 1, 0]).valid_arrangements(),
+            Ok(1)
+        );
+        assert_eq!(
+            record(vec![Unknown; 3], vec![3]).valid_arrangements(),
+            Ok(1)
+        );
+        assert_eq!(
+            record(vec![Unknown; 3], vec![1, 1]).valid_arrangements(),
+            Ok(1)
+        );
+        assert_eq!(
+            record(vec![Unknown; 3], vec![2, 1]).valid_arrangements(),
+            Ok(0)
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+
+use itertools::Itertools;
+use nom::{
+    bytes::complete::tag,
+    character::complete::{self, line_ending},
+    combinator::{all_consuming, map, opt},
+    multi::separated_list1,
+    sequence::{pair, separated_pair, terminated},
+    IResult,
+};
+
+use crate::{render, Record, Status};
+
+/// A grid of springs, indexed as `grid[row][col]`.
+pub type Grid = Vec<Vec<Status>>;
+
+/// A nonogram puzzle where every row and column clue is the damaged group
+/// list of a day 12 [`Record`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Nonogram {
+    pub rows: Vec<Vec<usize>>,
+    pub cols: Vec<Vec<usize>>,
+}
+
+/// Where a puzzle stopped parsing, counting lines from 1.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {} of the puzzle is not a clue", self.line)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Nonogram {
+    /// Parses the whole of `input`, which may end in a newline.
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        match all_consuming(terminated(parse_nonogram, opt(line_ending)))(input) {
+            Ok((_, nonogram)) => Ok(nonogram),
+            Err(nom::Err::Error(error) | nom::Err::Failure(error)) => {
+                let parsed = &input[..input.len() - error.input.len()];
+                Err(ParseError {
+                    line: parsed.matches('\n').count() + 1,
+                })
+            }
+            Err(nom::Err::Incomplete(_)) => unreachable!("complete parsers never ask for more"),
+        }
+    }
+
+    pub fn solve(&self) -> Option<Grid> {
+        let grid = vec![vec![Status::Unknown; self.cols.len()]; self.rows.len()];
+        self.search(grid)
+    }
+
+    fn search(&self, grid: Grid) -> Option<Grid> {
+        let grid = self.propagate(grid)?;
+
+        let Some((row, col)) = grid.iter().enumerate().find_map(|(row, line)| {
+            line.iter()
+                .position(|status| *status == Status::Unknown)
+                .map(|col| (row, col))
+        }) else {
+            return Some(grid);
+        };
+
+        // line solving got stuck, so guess a cell and backtrack on contradiction
+        [Status::Damaged, Status::Operational]
+            .into_iter()
+            .find_map(|guess| {
+                let mut next = grid.clone();
+                next[row][col] = guess;
+                self.search(next)
+            })
+    }
+
+    /// Line solves every row and column until nothing changes, or returns
+    /// `None` if some line has no valid arrangement left.
+    fn propagate(&self, mut grid: Grid) -> Option<Grid> {
+        loop {
+            let mut changed = false;
+
+            for (row, clue) in self.rows.iter().enumerate() {
+                let line = solve_line(&grid[row], clue)?;
+                if line != grid[row] {
+                    grid[row] = line;
+                    changed = true;
+                }
+            }
+
+            for (col, clue) in self.cols.iter().enumerate() {
+                let column = grid.iter().map(|line| line[col].clone()).collect_vec();
+                let line = solve_line(&column, clue)?;
+                if line != column {
+                    for (status, row) in line.into_iter().zip(grid.iter_mut()) {
+                        row[col] = status;
+                    }
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                return Some(grid);
+            }
+        }
+    }
+}
+
+fn solve_line(statuses: &[Status], clue: &[usize]) -> Option<Vec<Status>> {
+    Record {
+        statuses: statuses.to_vec(),
+        damaged_counts: clue.to_vec(),
+    }
+    .line_solve()
+}
+
+pub fn render_grid(grid: &Grid) -> String {
+    grid.iter().map(|line| render(line)).join("\n")
+}
+
+fn parse_clues(input: &str) -> IResult<&str, Vec<Vec<usize>>> {
+    separated_list1(
+        line_ending,
+        map(
+            separated_list1(tag(","), map(complete::u64, |n| n as usize)),
+            // a lone 0 marks an empty line
+            |groups| groups.into_iter().filter(|n| *n > 0).collect(),
+        ),
+    )(input)
+}
+
+/// Row clues, a blank line, then column clues, one comma separated clue
+/// per line.
+fn parse_nonogram(input: &str) -> IResult<&str, Nonogram> {
+    map(
+        separated_pair(parse_clues, pair(line_ending, line_ending), parse_clues),
+        |(rows, cols)| Nonogram { rows, cols },
+    )(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_grid, Nonogram, ParseError};
+
+    #[test]
+    fn solves_by_line_solving() {
+        let nonogram = Nonogram::parse(include_str!("bin/nonogram.txt")).unwrap();
+        let grid = nonogram.solve().expect("no solution");
+        assert_eq!(render_grid(&grid), ".#.#.\n#####\n#####\n.###.\n..#..");
+    }
+
+    #[test]
+    fn backtracks_when_stuck() {
+        // line solving alone cannot place anything in a diagonal pair
+        let nonogram = Nonogram::parse("1\n1\n0\n\n1\n1\n0").unwrap();
+        let grid = nonogram.solve().expect("no solution");
+        assert_eq!(render_grid(&grid), "#..\n.#.\n...");
+    }
+
+    #[test]
+    fn reports_unsolvable() {
+        let nonogram = Nonogram::parse("2\n0\n\n1\n0").unwrap();
+        assert!(nonogram.solve().is_none());
+    }
+
+    #[test]
+    fn rejects_leftover_input() {
+        assert!(Nonogram::parse("1\n0\n\n1\n0\n").is_ok());
+        assert!(Nonogram::parse("1\r\n0\r\n\r\n1\r\n0\r\n").is_ok());
+        assert_eq!(
+            Nonogram::parse("1\n0\n\n1\n0\nx"),
+            Err(ParseError { line: 6 })
+        );
+        assert_eq!(
+            Nonogram::parse("1\n0\n\n1\n0\n\n"),
+            Err(ParseError { line: 6 })
+        );
+        assert_eq!(Nonogram::parse("1\n0\n1"), Err(ParseError { line: 3 }));
+    }
+}
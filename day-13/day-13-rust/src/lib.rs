@@ -0,0 +1,562 @@
+use std::fmt;
+
+use nom::{
+    branch::alt,
+    character::complete::{self, line_ending},
+    combinator::map,
+    multi::{many1, separated_list1},
+    sequence::pair,
+    IResult,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    Ash,
+    Rock,
+}
+
+fn parse_item(input: &str) -> IResult<&str, Item> {
+    alt((
+        map(complete::char('.'), |_| Item::Ash),
+        map(complete::char('#'), |_| Item::Rock),
+    ))(input)
+}
+
+/// A symmetry of a pattern. Mirror lines are given as the number of rows
+/// above them or columns left of them; the rotations and diagonals only
+/// apply to square patterns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Line {
+    Horizontal(usize),
+    Vertical(usize),
+    /// Unchanged by a half turn.
+    Rotation180,
+    /// Unchanged by a quarter turn.
+    Rotation90,
+    /// Mirrored across the top left to bottom right diagonal.
+    Diagonal,
+    /// Mirrored across the top right to bottom left diagonal.
+    AntiDiagonal,
+}
+
+/// A cell that differs from its mirror image, both given as `(row, col)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Smudge {
+    pub cell: (usize, usize),
+    pub mirror: (usize, usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reflection {
+    pub line: Line,
+    pub smudges: Vec<Smudge>,
+}
+
+impl Reflection {
+    pub fn score(&self) -> usize {
+        self.line.score()
+    }
+}
+
+/// A pattern of at most 128 by 128 cells, with every row and column packed
+/// into a bitmask where a set bit is a rock.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    rows: Vec<u128>,
+    cols: Vec<u128>,
+}
+
+impl Pattern {
+    fn from_items(items: Vec<Vec<Item>>) -> Self {
+        let width = items.first().map_or(0, Vec::len);
+        assert!(
+            items.len() <= 128 && width <= 128,
+            "patterns are limited to 128x128"
+        );
+
+        let mut rows = vec![0; items.len()];
+        let mut cols = vec![0; width];
+        for (r, row) in items.iter().enumerate() {
+            for (c, item) in row.iter().enumerate() {
+                if *item == Item::Rock {
+                    rows[r] |= 1 << c;
+                    cols[c] |= 1 << r;
+                }
+            }
+        }
+
+        Pattern { rows, cols }
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Item {
+        if self.rows[row] & (1 << col) != 0 {
+            Item::Rock
+        } else {
+            Item::Ash
+        }
+    }
+
+    /// All symmetries that hold once exactly `smudges` cells are fixed,
+    /// horizontal lines first, then vertical lines, then the rotations and
+    /// diagonals.
+    pub fn reflections(&self, smudges: usize) -> Vec<Reflection> {
+        let horizontal = mirror_points(&self.rows, smudges).map(|i| Reflection {
+            line: Line::Horizontal(i),
+            smudges: smudge_cells(&self.rows, i),
+        });
+        let vertical = mirror_points(&self.cols, smudges).map(|i| Reflection {
+            line: Line::Vertical(i),
+            smudges: smudge_cells(&self.cols, i)
+                .into_iter()
+                .map(Smudge::transpose)
+                .collect(),
+        });
+
+        let (height, width) = (self.rows.len(), self.cols.len());
+        let square = height == width;
+        let n = height;
+
+        let others = [
+            (Line::Rotation180, true),
+            (Line::Rotation90, square),
+            (Line::Diagonal, square),
+            (Line::AntiDiagonal, square),
+        ]
+        .into_iter()
+        .filter(|(_, applies)| *applies)
+        .filter_map(|(line, _)| {
+            let cells = match line {
+                Line::Rotation180 => self.pair_smudges(|(r, c)| (height - 1 - r, width - 1 - c)),
+                Line::Rotation90 => self.orbit_smudges(|(r, c)| (c, n - 1 - r)),
+                Line::Diagonal => self.pair_smudges(|(r, c)| (c, r)),
+                Line::AntiDiagonal => self.pair_smudges(|(r, c)| (n - 1 - c, n - 1 - r)),
+                Line::Horizontal(_) | Line::Vertical(_) => unreachable!(),
+            };
+            (cells.len() == smudges).then_some(Reflection {
+                line,
+                smudges: cells,
+            })
+        });
+
+        horizontal.chain(vertical).chain(others).collect()
+    }
+
+    fn cells(&self) -> impl Iterator<Item = (usize, usize)> {
+        let width = self.cols.len();
+        (0..self.rows.len()).flat_map(move |r| (0..width).map(move |c| (r, c)))
+    }
+
+    /// Cells that differ from their image under the involution `mirror`,
+    /// counting every pair once.
+    fn pair_smudges(&self, mirror: impl Fn((usize, usize)) -> (usize, usize)) -> Vec<Smudge> {
+        self.cells()
+            .map(|cell| (cell, mirror(cell)))
+            .filter(|(cell, mirror)| cell < mirror)
+            .filter(|(cell, mirror)| self.get(cell.0, cell.1) != self.get(mirror.0, mirror.1))
+            .map(|(cell, mirror)| Smudge { cell, mirror })
+            .collect()
+    }
+
+    /// Cells that have to change for every orbit of `rotate` to hold a
+    /// single item, choosing the majority item of each orbit.
+    fn orbit_smudges(&self, rotate: impl Fn((usize, usize)) -> (usize, usize)) -> Vec<Smudge> {
+        let mut seen = vec![false; self.rows.len() * self.cols.len()];
+        let mut cells = Vec::new();
+
+        for start in self.cells() {
+            if seen[start.0 * self.cols.len() + start.1] {
+                continue;
+            }
+
+            let mut orbit = vec![start];
+            let mut next = rotate(start);
+            while next != start {
+                orbit.push(next);
+                next = rotate(next);
+            }
+            for cell in orbit.iter() {
+                seen[cell.0 * self.cols.len() + cell.1] = true;
+            }
+
+            let rocks = orbit
+                .iter()
+                .filter(|(r, c)| self.get(*r, *c) == Item::Rock)
+                .count();
+            let majority = if rocks * 2 == orbit.len() {
+                self.get(start.0, start.1)
+            } else if rocks * 2 > orbit.len() {
+                Item::Rock
+            } else {
+                Item::Ash
+            };
+            let mirror = *orbit
+                .iter()
+                .find(|(r, c)| self.get(*r, *c) == majority)
+                .unwrap();
+
+            cells.extend(
+                orbit
+                    .into_iter()
+                    .filter(|(r, c)| self.get(*r, *c) != majority)
+                    .map(|cell| Smudge { cell, mirror }),
+            );
+        }
+
+        cells
+    }
+
+    /// The first mirror line with exactly `smudges` differing cells, found
+    /// without allocating.
+    pub fn first_line(&self, smudges: usize) -> Option<Line> {
+        mirror_points(&self.rows, smudges)
+            .map(Line::Horizontal)
+            .chain(mirror_points(&self.cols, smudges).map(Line::Vertical))
+            .next()
+    }
+
+    pub fn mirror_score(&self, smudges: usize) -> Option<usize> {
+        self.first_line(smudges).map(Line::score)
+    }
+
+    /// The pattern with every smudge of `reflection` flipped to match its
+    /// mirror, making the reflection exact.
+    pub fn corrected(&self, reflection: &Reflection) -> Pattern {
+        let mut corrected = self.clone();
+        for Smudge { cell: (r, c), .. } in reflection.smudges.iter() {
+            corrected.rows[*r] ^= 1 << c;
+            corrected.cols[*c] ^= 1 << r;
+        }
+
+        corrected
+    }
+
+    /// Renders the pattern with `line` drawn in as a row of `-` or a column
+    /// of `|`.
+    pub fn render(&self, line: Option<Line>) -> String {
+        let mut out = String::new();
+
+        for r in 0..self.rows.len() {
+            if line == Some(Line::Horizontal(r)) {
+                out.extend(std::iter::repeat_n('-', self.cols.len()));
+                out.push('\n');
+            }
+
+            for c in 0..self.cols.len() {
+                if line == Some(Line::Vertical(c)) {
+                    out.push('|');
+                }
+                out.push(match self.get(r, c) {
+                    Item::Ash => '.',
+                    Item::Rock => '#',
+                });
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+impl Line {
+    /// The puzzle score, which only counts mirror lines.
+    pub fn score(self) -> usize {
+        match self {
+            Line::Horizontal(rows) => rows * 100,
+            Line::Vertical(cols) => cols,
+            _ => 0,
+        }
+    }
+}
+
+impl Smudge {
+    fn transpose(self) -> Self {
+        Smudge {
+            cell: (self.cell.1, self.cell.0),
+            mirror: (self.mirror.1, self.mirror.0),
+        }
+    }
+}
+
+/// Every index `i` where mirroring `lines[..i]` onto `lines[i..]` flips
+/// exactly `smudges` bits.
+fn mirror_points(lines: &[u128], smudges: usize) -> impl Iterator<Item = usize> + '_ {
+    (1..lines.len()).filter(move |i| {
+        let mut differing = 0;
+        for (left, right) in lines[..*i].iter().rev().zip(&lines[*i..]) {
+            differing += (left ^ right).count_ones() as usize;
+            if differing > smudges {
+                return false;
+            }
+        }
+
+        differing == smudges
+    })
+}
+
+/// The bits that differ when mirroring `lines` at `i`, given as
+/// `(index in lines, bit)`.
+fn smudge_cells(lines: &[u128], i: usize) -> Vec<Smudge> {
+    let mut cells = Vec::new();
+    for (offset, (left, right)) in lines[..i].iter().rev().zip(&lines[i..]).enumerate() {
+        let mut diff = left ^ right;
+        while diff != 0 {
+            let bit = diff.trailing_zeros() as usize;
+            cells.push(Smudge {
+                cell: (i - 1 - offset, bit),
+                mirror: (i + offset, bit),
+            });
+            diff &= diff - 1;
+        }
+    }
+
+    cells
+}
+
+fn parse_pattern(input: &str) -> IResult<&str, Pattern> {
+    map(
+        separated_list1(line_ending, many1(parse_item)),
+        Pattern::from_items,
+    )(input)
+}
+
+#[derive(Debug)]
+pub struct Patterns {
+    pub patterns: Vec<Pattern>,
+}
+
+impl Patterns {
+    pub fn parse(input: &str) -> Self {
+        parse_patterns(input).expect("failed to parse").1
+    }
+
+    /// The score of every pattern, failing with the pattern's index when it
+    /// has no mirror line.
+    pub fn mirror_scores(
+        &self,
+        smudges: usize,
+    ) -> impl Iterator<Item = Result<usize, NoReflection>> + '_ {
+        self.patterns.iter().enumerate().map(move |(pattern, p)| {
+            p.mirror_score(smudges)
+                .ok_or(NoReflection { pattern, smudges })
+        })
+    }
+
+    /// Sums the pattern scores. [`Mode::Strict`] stops at the first pattern
+    /// without a mirror line, [`Mode::Lenient`] skips it and lists it in
+    /// [`Score::skipped`].
+    pub fn mirror_score(&self, smudges: usize, mode: Mode) -> Result<Score, NoReflection> {
+        let mut score = Score::default();
+
+        for result in self.mirror_scores(smudges) {
+            match (result, mode) {
+                (Ok(value), _) => score.total += value,
+                (Err(error), Mode::Strict) => return Err(error),
+                (Err(error), Mode::Lenient) => score.skipped.push(error),
+            }
+        }
+
+        Ok(score)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Strict,
+    Lenient,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Score {
+    pub total: usize,
+    pub skipped: Vec<NoReflection>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoReflection {
+    pub pattern: usize,
+    pub smudges: usize,
+}
+
+impl fmt::Display for NoReflection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pattern {} has no reflection with {} smudge(s)",
+            self.pattern, self.smudges
+        )
+    }
+}
+
+impl std::error::Error for NoReflection {}
+
+fn parse_patterns(input: &str) -> IResult<&str, Patterns> {
+    map(
+        separated_list1(pair(line_ending, line_ending), parse_pattern),
+        |patterns| Patterns { patterns },
+    )(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Line, Mode, NoReflection, Patterns, Score, Smudge};
+
+    #[test]
+    fn examples() {
+        let patterns = Patterns::parse(include_str!("bin/test.txt"));
+        assert_eq!(patterns.mirror_score(0, Mode::Strict).unwrap().total, 405);
+        assert_eq!(patterns.mirror_score(1, Mode::Strict).unwrap().total, 400);
+    }
+
+    #[test]
+    fn reflections() {
+        let patterns = Patterns::parse(include_str!("bin/test.txt"));
+        let lines = |pattern: usize, smudges: usize| {
+            patterns.patterns[pattern]
+                .reflections(smudges)
+                .into_iter()
+                .map(|r| r.line)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(lines(0, 0), vec![Line::Vertical(5)]);
+        assert_eq!(lines(0, 1), vec![Line::Horizontal(3)]);
+        assert_eq!(lines(1, 0), vec![Line::Horizontal(4)]);
+        assert_eq!(lines(1, 1), vec![Line::Horizontal(1)]);
+    }
+
+    #[test]
+    fn smudges() {
+        let patterns = Patterns::parse(include_str!("bin/test.txt"));
+
+        let reflection = &patterns.patterns[0].reflections(1)[0];
+        assert_eq!(
+            reflection.smudges,
+            vec![Smudge {
+                cell: (0, 0),
+                mirror: (5, 0)
+            }]
+        );
+
+        let reflection = &patterns.patterns[1].reflections(1)[0];
+        assert_eq!(
+            reflection.smudges,
+            vec![Smudge {
+                cell: (0, 4),
+                mirror: (1, 4)
+            }]
+        );
+    }
+
+    #[test]
+    fn corrected() {
+        let patterns = Patterns::parse(include_str!("bin/test.txt"));
+        let pattern = &patterns.patterns[0];
+        let reflection = &pattern.reflections(1)[0];
+
+        let corrected = pattern.corrected(reflection);
+        let exact = corrected.reflections(0);
+        assert!(exact.iter().any(|r| r.line == reflection.line));
+
+        assert_eq!(
+            corrected.render(Some(reflection.line)),
+            "..##..##.\n..#.##.#.\n##......#\n---------\n##......#\n..#.##.#.\n..##..##.\n#.#.##.#.\n"
+        );
+        assert_eq!(
+            pattern.render(Some(Line::Vertical(5))).lines().next(),
+            Some("#.##.|.##.")
+        );
+    }
+
+    #[test]
+    fn wide_patterns() {
+        let half = "#.".repeat(32);
+        let row = format!("{half}{}", half.chars().rev().collect::<String>());
+        let flipped = format!(".{}.", &row[1..127]);
+        let patterns = Patterns::parse(&format!("{row}\n{flipped}"));
+        let pattern = &patterns.patterns[0];
+
+        assert_eq!(pattern.first_line(0), Some(Line::Vertical(64)));
+        assert_eq!(pattern.first_line(2), Some(Line::Horizontal(1)));
+    }
+
+    #[test]
+    fn rotations_and_diagonals() {
+        let symmetries = |input: &str, smudges: usize| {
+            Patterns::parse(input).patterns[0]
+                .reflections(smudges)
+                .into_iter()
+                .map(|r| r.line)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            symmetries("#..\n.#.\n..#", 0),
+            vec![Line::Rotation180, Line::Diagonal, Line::AntiDiagonal]
+        );
+        assert_eq!(
+            symmetries("#.#\n...\n#.#", 0),
+            vec![
+                Line::Rotation180,
+                Line::Rotation90,
+                Line::Diagonal,
+                Line::AntiDiagonal
+            ]
+        );
+        assert_eq!(symmetries("##.\n...\n.##", 0), vec![Line::Rotation180]);
+        assert_eq!(
+            symmetries("##.\n...\n.##", 2),
+            vec![
+                Line::Horizontal(1),
+                Line::Horizontal(2),
+                Line::Diagonal,
+                Line::AntiDiagonal
+            ]
+        );
+        assert_eq!(
+            symmetries("#..\n...", 1),
+            vec![Line::Horizontal(1), Line::Vertical(1), Line::Rotation180]
+        );
+    }
+
+    #[test]
+    fn quarter_turn_smudges() {
+        let patterns = Patterns::parse("#.#\n...\n#..");
+        let pattern = &patterns.patterns[0];
+        let reflection = pattern
+            .reflections(1)
+            .into_iter()
+            .find(|r| r.line == Line::Rotation90)
+            .expect("no quarter turn symmetry");
+
+        assert_eq!(reflection.smudges.len(), 1);
+        assert_eq!(reflection.smudges[0].cell, (2, 2));
+        assert!(pattern
+            .corrected(&reflection)
+            .reflections(0)
+            .iter()
+            .any(|r| r.line == Line::Rotation90));
+    }
+
+    #[test]
+    fn patterns_without_reflection() {
+        let input = format!("{}\n\n#.#\n.#.", include_str!("bin/test.txt").trim_end());
+        let patterns = Patterns::parse(&input);
+        let missing = NoReflection {
+            pattern: 2,
+            smudges: 0,
+        };
+
+        assert_eq!(
+            patterns.mirror_scores(0).collect::<Vec<_>>(),
+            vec![Ok(5), Ok(400), Err(missing)]
+        );
+        assert_eq!(patterns.mirror_score(0, Mode::Strict), Err(missing));
+        assert_eq!(
+            patterns.mirror_score(0, Mode::Lenient),
+            Ok(Score {
+                total: 405,
+                skipped: vec![missing]
+            })
+        );
+    }
+}
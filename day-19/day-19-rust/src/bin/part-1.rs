@@ -1,276 +1,235 @@
-use std::collections::{HashMap, HashSet};
-
-use nom::{
-    branch::alt,
-    bytes::complete::tag,
-    character::complete::{self, alpha1, line_ending},
-    combinator::{map, opt},
-    multi::separated_list1,
-    sequence::{delimited, pair, separated_pair, terminated, tuple},
-    IResult,
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
 };
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-struct WorkflowName(String);
-
-type Workflows = HashMap<WorkflowName, Workflow>;
+use day_19_rust::{
+    classify::{classify, read_parts, ClassifyError, Tally},
+    parse_system, report, split_system, System, MAX_HOPS,
+};
 
-#[derive(Debug)]
-struct System {
-    workflows: Workflows,
-    parts: Vec<Part>,
+/// The parts to classify, read from `--parts` or else from the part list in
+/// the input.
+fn parts(path: Option<&str>, included: &'static str) -> Box<dyn BufRead> {
+    match path {
+        Some(path) => Box::new(BufReader::new(
+            File::open(path).unwrap_or_else(|e| panic!("failed to open {path}: {e}")),
+        )),
+        None => Box::new(included.as_bytes()),
+    }
 }
 
-impl System {
-    fn accepted_parts(&self) -> HashSet<&Part> {
-        let mut set = HashSet::new();
-
-        let workflow = self.workflows.get(&WorkflowName("in".to_owned())).unwrap();
-
-        for part in self.parts.iter() {
-            let transition = workflow.execute(part, &self.workflows);
-
-            if transition == Transition::Accepted {
-                set.insert(part);
-            }
-        }
-
-        set
+/// Where to write parts to, or nowhere without a path.
+fn output(path: Option<&str>) -> Box<dyn Write> {
+    match path {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).unwrap_or_else(|e| panic!("failed to create {path}: {e}")),
+        )),
+        None => Box::new(io::sink()),
     }
+}
 
-    fn sum_rating_of_accepted_parts(&self) -> u32 {
-        self.accepted_parts()
-            .into_iter()
-            .map(|p| p.x + p.m + p.a + p.s)
-            .sum()
+fn trace(system: &System, parts: impl BufRead, max_hops: usize) -> Result<(), ClassifyError> {
+    for part in read_parts(parts) {
+        let (_, part) = part?;
+        println!("{part}: {}", system.trace(&part, max_hops)?);
     }
+    Ok(())
 }
 
-#[derive(Debug)]
-struct Workflow {
-    rules: Vec<Rule>,
-    end: Transition,
+fn arg(flag: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != flag).nth(1)
 }
 
-impl Workflow {
-    fn execute(&self, part: &Part, workflows: &Workflows) -> Transition {
-        use Transition::*;
-
-        for rule in self.rules.iter() {
-            match rule.execute(part) {
-                Some(Accepted) => return Accepted,
-                Some(Rejected) => return Rejected,
-                Some(Pointer(name)) => {
-                    let workflow = workflows.get(name).unwrap();
-                    return workflow.execute(part, workflows);
-                }
-                None => {}
-            };
-        }
+fn main() {
+    let (included, workflows) = split_system(include_str!("input.txt")).expect("no part list");
+    let (_, system) = parse_system(workflows).expect("failed to parse");
 
-        match &self.end {
-            Accepted => Accepted,
-            Rejected => Rejected,
-            Pointer(name) => {
-                let workflow = workflows.get(name).unwrap();
-                workflow.execute(part, workflows)
-            }
-        }
+    if report(&system.validate(&system.bounds(1..=4000, &[]))) {
+        std::process::exit(1);
     }
-}
 
-#[derive(Debug)]
-struct Rule {
-    cond: Condition,
-    result: Transition,
-}
+    let max_hops = arg("--max-hops")
+        .map(|n| n.parse().expect("--max-hops expects a number"))
+        .unwrap_or(MAX_HOPS);
+    let path = arg("--parts");
 
-impl Rule {
-    fn execute(&self, part: &Part) -> Option<&Transition> {
-        if self.cond.apply(part) {
-            Some(&self.result)
-        } else {
-            None
+    if std::env::args().any(|arg| arg == "--trace") {
+        if let Err(error) = trace(&system, parts(path.as_deref(), included), max_hops) {
+            eprintln!("Error: {error}");
+            std::process::exit(1);
         }
     }
-}
 
-#[derive(Debug)]
-struct Condition {
-    left: Rating,
-    comp: Comparison,
-    right: u32,
-}
-
-impl Condition {
-    fn apply(&self, part: &Part) -> bool {
-        let left = self.left.get(part);
-        self.comp.apply(left, self.right)
-    }
-}
-
-#[derive(Debug)]
-enum Rating {
-    X,
-    M,
-    A,
-    S,
-}
-impl Rating {
-    fn get(&self, part: &Part) -> u32 {
-        use Rating::*;
-        match self {
-            X => part.x,
-            M => part.m,
-            A => part.a,
-            S => part.s,
+    let mut accepted = output(arg("--accepted").as_deref());
+    let mut rejected = output(arg("--rejected").as_deref());
+    let result = classify(
+        &system,
+        parts(path.as_deref(), included),
+        &mut accepted,
+        &mut rejected,
+        max_hops,
+    )
+    .and_then(|tally| {
+        accepted.flush()?;
+        rejected.flush()?;
+        Ok(tally)
+    });
+
+    match result {
+        Ok(Tally {
+            accepted,
+            rejected,
+            accepted_rating,
+        }) => {
+            eprintln!("{accepted} accepted, {rejected} rejected");
+            println!("Answer: {accepted_rating}");
         }
-    }
-}
-
-#[derive(Debug)]
-enum Comparison {
-    LessThan,
-    GreaterThan,
-}
-impl Comparison {
-    fn apply(&self, left: u32, right: u32) -> bool {
-        use Comparison::*;
-        match self {
-            LessThan => left < right,
-            GreaterThan => left > right,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            std::process::exit(1);
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-enum Transition {
-    Pointer(WorkflowName),
-    Rejected,
-    Accepted,
-}
-
-#[derive(Debug, PartialEq, Eq, Hash)]
-struct Part {
-    x: u32,
-    m: u32,
-    a: u32,
-    s: u32,
-}
-
-fn parse_system(input: &str) -> IResult<&str, System> {
-    map(
-        separated_pair(parse_workflows, pair(line_ending, line_ending), parse_parts),
-        |(workflows, parts)| System { workflows, parts },
-    )(input)
-}
-
-fn parse_workflows(input: &str) -> IResult<&str, Workflows> {
-    map(separated_list1(line_ending, parse_workflow), |workflows| {
-        workflows.into_iter().collect()
-    })(input)
-}
-
-fn parse_workflow(input: &str) -> IResult<&str, (WorkflowName, Workflow)> {
-    pair(
-        map(alpha1, |n: &str| WorkflowName(n.to_owned())),
-        delimited(
-            complete::char('{'),
-            map(
-                separated_pair(parse_rules, complete::char(','), parse_transition),
-                |(rules, end)| Workflow { rules, end },
-            ),
-            complete::char('}'),
-        ),
-    )(input)
-}
-
-fn parse_rules(input: &str) -> IResult<&str, Vec<Rule>> {
-    separated_list1(complete::char(','), parse_rule)(input)
-}
-
-fn parse_rule(input: &str) -> IResult<&str, Rule> {
-    map(
-        separated_pair(parse_cond, complete::char(':'), parse_transition),
-        |(cond, result)| Rule { cond, result },
-    )(input)
-}
+#[cfg(test)]
+mod tests {
+    use day_19_rust::{
+        parse_system, split_system, Diagnostic, Rating, Transition, WorkflowName, MAX_HOPS,
+    };
+
+    use day_19_rust::classify::classify;
+
+    /// The sum of the ratings of the accepted parts, classifying the part
+    /// list separately from the workflows.
+    fn sum_rating_of_accepted_parts(input: &str) -> u64 {
+        let (parts, workflows) = split_system(input).unwrap();
+        let (_, system) = parse_system(workflows).expect("failed to parse");
+        classify(
+            &system,
+            parts.as_bytes(),
+            &mut std::io::sink(),
+            &mut std::io::sink(),
+            MAX_HOPS,
+        )
+        .unwrap()
+        .accepted_rating
+    }
 
-fn parse_cond(input: &str) -> IResult<&str, Condition> {
-    use Comparison::*;
-    alt((
-        map(
-            separated_pair(parse_rating, complete::char('<'), complete::u32),
-            |(left, right)| Condition {
-                left,
-                comp: LessThan,
-                right,
-            },
-        ),
-        map(
-            separated_pair(parse_rating, complete::char('>'), complete::u32),
-            |(left, right)| Condition {
-                left,
-                comp: GreaterThan,
-                right,
-            },
-        ),
-    ))(input)
-}
+    fn rating(name: &str) -> Rating {
+        Rating(name.to_owned())
+    }
 
-fn parse_rating(input: &str) -> IResult<&str, Rating> {
-    use Rating::*;
-    alt((
-        map(tag("x"), |_| X),
-        map(tag("m"), |_| M),
-        map(tag("a"), |_| A),
-        map(tag("s"), |_| S),
-    ))(input)
-}
+    #[test]
+    fn example() {
+        assert_eq!(
+            sum_rating_of_accepted_parts(include_str!("test.txt")),
+            19114
+        );
+    }
 
-fn parse_transition(input: &str) -> IResult<&str, Transition> {
-    use Transition::*;
-    alt((
-        map(tag("A"), |_| Accepted),
-        map(tag("R"), |_| Rejected),
-        map(alpha1, |n: &str| Pointer(WorkflowName(n.to_owned()))),
-    ))(input)
-}
+    #[test]
+    fn crlf_line_endings() {
+        assert_eq!(
+            sum_rating_of_accepted_parts(&include_str!("test.txt").replace('\n', "\r\n")),
+            19114
+        );
+    }
 
-fn parse_parts(input: &str) -> IResult<&str, Vec<Part>> {
-    separated_list1(line_ending, parse_part)(input)
-}
+    #[test]
+    fn traces() {
+        let (_, system) = parse_system(include_str!("test.txt")).expect("failed to parse");
+
+        let traces = system
+            .parts
+            .iter()
+            .map(|part| system.trace(part, MAX_HOPS).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            traces,
+            vec![
+                "in -> qqz (s>2770) -> qs -> lnx (m>1548) -> A",
+                "in (s<1351) -> px -> rfg (s<537) -> gd -> R",
+                "in -> qqz (m<1801) -> hdj -> pv -> A",
+                "in (s<1351) -> px (a<2006) -> qkq -> crn -> R",
+                "in (s<1351) -> px -> rfg -> A",
+            ]
+        );
+
+        let trace = system.trace(&system.parts[2], MAX_HOPS).unwrap();
+        assert_eq!(trace.result, Transition::Accepted);
+        assert_eq!(trace.steps[1].evaluated.len(), 2);
+        assert_eq!(trace.steps[3].evaluated.len(), 1);
+        assert!(trace.steps[3].matched.is_none());
+    }
 
-fn parse_part(input: &str) -> IResult<&str, Part> {
-    delimited(
-        complete::char('{'),
-        map(
-            tuple((
-                parse_key('x'),
-                parse_key('m'),
-                parse_key('a'),
-                parse_key('s'),
-            )),
-            |(x, m, a, s)| Part { x, m, a, s },
-        ),
-        complete::char('}'),
-    )(input)
-}
+    #[test]
+    fn undefined_workflows() {
+        let (_, system) =
+            parse_system("in{x<10:px,A}\n\n{x=1,m=2,a=3,s=4}").expect("failed to parse");
+        assert_eq!(
+            system.validate(&system.bounds(1..=4000, &[])),
+            vec![Diagnostic::UndefinedWorkflow {
+                from: WorkflowName("in".to_owned()),
+                to: WorkflowName("px".to_owned())
+            }]
+        );
+
+        let (_, system) =
+            parse_system("px{x<10:A,R}\n\n{x=1,m=2,a=3,s=4}").expect("failed to parse");
+        assert!(system
+            .validate(&system.bounds(1..=4000, &[]))
+            .contains(&Diagnostic::MissingEntry));
+    }
 
-fn parse_key(c: char) -> impl Fn(&str) -> IResult<&str, u32> {
-    move |input: &str| {
-        map(
-            terminated(
-                separated_pair(complete::char(c), complete::char('='), complete::u32),
-                opt(complete::char(',')),
-            ),
-            |(_, n)| n,
-        )(input)
+    #[test]
+    fn arbitrary_ratings() {
+        let input = "in{weight>10:heavy,colour<3:A,R}\nheavy{size<5:A,R}\n\n{size=1,weight=20,colour=5}\n{size=9,weight=20,colour=1}\n{weight=1,colour=1,size=0}";
+        let (_, system) = parse_system(input).expect("failed to parse");
+        assert_eq!(
+            system.dimensions(),
+            vec![rating("size"), rating("weight"), rating("colour")]
+        );
+
+        assert_eq!(sum_rating_of_accepted_parts(input), 26 + 2);
+        assert_eq!(
+            system
+                .trace(&system.parts[1], MAX_HOPS)
+                .unwrap()
+                .to_string(),
+            "in (weight>10) -> heavy -> R"
+        );
+
+        let (_, system) = parse_system("in{weight>10:A,R}\n\n{size=1}").expect("failed to parse");
+        assert_eq!(
+            system.validate(&system.bounds(1..=4000, &[])),
+            vec![Diagnostic::MissingRating {
+                part: 0,
+                rating: rating("weight")
+            }]
+        );
     }
-}
 
-fn main() {
-    let (_, system) = parse_system(include_str!("input.txt")).expect("failed to parse");
-    let answer = system.sum_rating_of_accepted_parts();
-    println!("Answer: {answer}");
+    #[test]
+    fn compound_conditions() {
+        let (_, system) = parse_system(
+            "in{x<=10&&m>=5:A,a==3||s!=7&&(x==1||x==2):A,R}\n\n{x=10,m=5,a=0,s=7}\n{x=11,m=5,a=3,s=7}\n{x=1,m=0,a=0,s=8}\n{x=1,m=0,a=0,s=7}",
+        )
+        .expect("failed to parse");
+
+        let program = system.compile().expect("failed to compile workflows");
+        let accepted = system
+            .parts
+            .iter()
+            .map(|part| program.accepts(part, MAX_HOPS).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(accepted, vec![true, true, true, false]);
+        assert_eq!(
+            system
+                .trace(&system.parts[2], MAX_HOPS)
+                .unwrap()
+                .to_string(),
+            "in (a==3||s!=7&&(x==1||x==2)) -> A"
+        );
+    }
 }
@@ -0,0 +1,231 @@
+use std::{
+    fmt,
+    io::{self, BufRead, Write},
+};
+
+use rayon::prelude::*;
+
+use crate::{
+    parse_part,
+    program::{CompileError, Program},
+    CycleError, Part, Rating, System,
+};
+
+/// How many parts are read in before classifying them in parallel.
+const CHUNK_SIZE: usize = 4096;
+
+/// How many parts ended up at each terminal, counting duplicates.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub accepted: usize,
+    pub rejected: usize,
+    /// The sum of every rating of every accepted part.
+    pub accepted_rating: u64,
+}
+
+#[derive(Debug)]
+pub enum ClassifyError {
+    Io(io::Error),
+    /// A line, counting from 1, that is not a part.
+    Parse {
+        line: usize,
+    },
+    MissingRating {
+        line: usize,
+        rating: Rating,
+    },
+    Cycle(CycleError),
+    Compile(CompileError),
+}
+
+impl fmt::Display for ClassifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClassifyError::Io(error) => write!(f, "{error}"),
+            ClassifyError::Parse { line } => write!(f, "line {line} is not a part"),
+            ClassifyError::MissingRating { line, rating } => {
+                write!(f, "the part on line {line} has no `{}` rating", rating.0)
+            }
+            ClassifyError::Cycle(error) => write!(f, "{error}"),
+            ClassifyError::Compile(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for ClassifyError {}
+
+impl From<io::Error> for ClassifyError {
+    fn from(error: io::Error) -> Self {
+        ClassifyError::Io(error)
+    }
+}
+
+impl From<CycleError> for ClassifyError {
+    fn from(error: CycleError) -> Self {
+        ClassifyError::Cycle(error)
+    }
+}
+
+impl From<CompileError> for ClassifyError {
+    fn from(error: CompileError) -> Self {
+        ClassifyError::Compile(error)
+    }
+}
+
+/// Reads one part per line, skipping blank lines, along with its line
+/// number.
+pub fn read_parts<R: BufRead>(
+    reader: R,
+) -> impl Iterator<Item = Result<(usize, Part), ClassifyError>> {
+    reader.lines().enumerate().filter_map(|(index, line)| {
+        let line = match line {
+            Ok(line) => line,
+            Err(error) => return Some(Err(error.into())),
+        };
+        if line.trim().is_empty() {
+            return None;
+        }
+
+        Some(match parse_part(line.trim()) {
+            Ok(("", part)) => Ok((index + 1, part)),
+            _ => Err(ClassifyError::Parse { line: index + 1 }),
+        })
+    })
+}
+
+fn accepts(
+    program: &Program,
+    part: &Part,
+    max_hops: usize,
+    line: usize,
+) -> Result<bool, ClassifyError> {
+    let ranges = program
+        .ranges(part)
+        .map_err(|rating| ClassifyError::MissingRating { line, rating })?;
+    Ok(program.accepts_ranges(part, ranges, max_hops)?)
+}
+
+/// Runs every part read from `parts` through the workflows, compiled once
+/// up front, a chunk at a time spread over all threads, and writes it to
+/// `accepted` or `rejected` in input order.
+pub fn classify<R: BufRead, A: Write, B: Write>(
+    system: &System,
+    parts: R,
+    accepted: &mut A,
+    rejected: &mut B,
+    max_hops: usize,
+) -> Result<Tally, ClassifyError> {
+    let program = system.compile()?;
+    let mut tally = Tally::default();
+    let mut parts = read_parts(parts);
+
+    loop {
+        let chunk = parts
+            .by_ref()
+            .take(CHUNK_SIZE)
+            .collect::<Result<Vec<_>, _>>()?;
+        if chunk.is_empty() {
+            break;
+        }
+
+        let results = chunk
+            .into_par_iter()
+            .map(|(line, part)| {
+                let passed = accepts(&program, &part, max_hops, line)?;
+                Ok((part, passed))
+            })
+            .collect::<Result<Vec<_>, ClassifyError>>()?;
+
+        for (part, passed) in results {
+            if passed {
+                tally.accepted += 1;
+                tally.accepted_rating += part
+                    .ratings
+                    .iter()
+                    .map(|(_, value)| value.start())
+                    .sum::<u64>();
+                writeln!(accepted, "{part}")?;
+            } else {
+                tally.rejected += 1;
+                writeln!(rejected, "{part}")?;
+            }
+        }
+    }
+
+    Ok(tally)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{parse_system, MAX_HOPS};
+
+    use super::{classify, ClassifyError, Tally};
+
+    #[test]
+    fn counts_duplicates() {
+        let (workflows, parts) = include_str!("bin/test.txt").split_once("\n\n").unwrap();
+        let (_, system) = parse_system(workflows).expect("failed to parse");
+
+        let twice = format!("{parts}\n\n{parts}\n");
+        let mut accepted = Vec::new();
+        let mut rejected = Vec::new();
+        let tally = classify(
+            &system,
+            twice.as_bytes(),
+            &mut accepted,
+            &mut rejected,
+            MAX_HOPS,
+        )
+        .unwrap();
+
+        assert_eq!(
+            tally,
+            Tally {
+                accepted: 6,
+                rejected: 4,
+                accepted_rating: 2 * 19114,
+            }
+        );
+        assert_eq!(
+            String::from_utf8(accepted)
+                .unwrap()
+                .lines()
+                .collect::<Vec<_>>(),
+            [
+                "{x=787,m=2655,a=1222,s=2876}",
+                "{x=2036,m=264,a=79,s=2244}",
+                "{x=2127,m=1623,a=2188,s=1013}",
+            ]
+            .repeat(2)
+        );
+        assert_eq!(String::from_utf8(rejected).unwrap().lines().count(), 4);
+    }
+
+    #[test]
+    fn reports_bad_lines() {
+        let (_, system) = parse_system("in{x<10:A,R}").expect("failed to parse");
+
+        let result = classify(
+            &system,
+            "{x=1}\n{x=2\n".as_bytes(),
+            &mut io::sink(),
+            &mut io::sink(),
+            MAX_HOPS,
+        );
+        assert!(matches!(result, Err(ClassifyError::Parse { line: 2 })));
+
+        let result = classify(
+            &system,
+            "{x=1}\n\n{m=2}\n".as_bytes(),
+            &mut io::sink(),
+            &mut io::sink(),
+            MAX_HOPS,
+        );
+        assert!(matches!(
+            result,
+            Err(ClassifyError::MissingRating { line: 3, .. })
+        ));
+    }
+}
@@ -0,0 +1,1756 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    ops::RangeInclusive,
+};
+
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{self, alpha1, line_ending},
+    combinator::{map, opt, recognize, verify},
+    multi::{many0, separated_list0, separated_list1},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
+    IResult,
+};
+
+pub mod classify;
+pub mod program;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct WorkflowName(pub String);
+
+pub type Workflows = HashMap<WorkflowName, Workflow>;
+
+#[derive(Debug)]
+pub struct System {
+    pub workflows: Workflows,
+    /// The part list, every part a box holding a single value per rating.
+    pub parts: Vec<Part>,
+}
+
+impl System {
+    /// Checks the workflows without running them. Every part starts in
+    /// `bounds` when looking for rules that can never match.
+    pub fn validate(&self, bounds: &Part) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let start = WorkflowName("in".to_owned());
+
+        let mut names = self.workflows.keys().collect::<Vec<_>>();
+        names.sort();
+
+        let mut unbounded = self
+            .workflow_ratings()
+            .filter(|rating| bounds.try_get(rating).is_none())
+            .cloned()
+            .collect::<Vec<_>>();
+        unbounded.sort();
+        unbounded.dedup();
+        diagnostics.extend(unbounded.into_iter().map(Diagnostic::UnboundedRating));
+
+        for (index, part) in self.parts.iter().enumerate() {
+            diagnostics.extend(self.missing_ratings(part).into_iter().map(|rating| {
+                Diagnostic::MissingRating {
+                    part: index,
+                    rating,
+                }
+            }));
+        }
+
+        if !self.workflows.contains_key(&start) {
+            diagnostics.push(Diagnostic::MissingEntry);
+        }
+
+        for name in names.iter() {
+            for target in self.workflows[*name].targets() {
+                if !self.workflows.contains_key(target) {
+                    diagnostics.push(Diagnostic::UndefinedWorkflow {
+                        from: (*name).clone(),
+                        to: target.clone(),
+                    });
+                }
+            }
+        }
+
+        let mut reachable = HashSet::new();
+        let mut stack = vec![&start];
+        while let Some(name) = stack.pop() {
+            if let Some(workflow) = self.workflows.get(name) {
+                if reachable.insert(name) {
+                    stack.extend(workflow.targets());
+                }
+            }
+        }
+        for name in names.iter() {
+            if !reachable.contains(name) {
+                diagnostics.push(Diagnostic::Unreachable((*name).clone()));
+            }
+        }
+
+        let mut visited = HashSet::new();
+        for name in names.iter() {
+            self.find_cycles(name, &mut visited, &mut diagnostics);
+        }
+
+        if diagnostics.iter().any(|d| {
+            matches!(
+                d,
+                Diagnostic::UnboundedRating(_) | Diagnostic::MissingRating { .. }
+            )
+        }) {
+            return diagnostics;
+        }
+
+        for name in names.iter() {
+            let mut part = Some(vec![bounds.clone()]);
+            for (index, rule) in self.workflows[*name].rules.iter().enumerate() {
+                let Some(current) = part else {
+                    diagnostics.push(Diagnostic::ShadowedRule {
+                        workflow: (*name).clone(),
+                        rule: index,
+                    });
+                    continue;
+                };
+
+                part = match rule.execute(&current) {
+                    (ApplyResult::Split { fails, .. }, _) => Some(fails),
+                    (ApplyResult::Passed, _) => None,
+                    (ApplyResult::Failed, _) => {
+                        diagnostics.push(Diagnostic::ShadowedRule {
+                            workflow: (*name).clone(),
+                            rule: index,
+                        });
+                        Some(current)
+                    }
+                };
+            }
+        }
+
+        diagnostics
+    }
+
+    /// Every rating named in the part list, in order of first appearance,
+    /// followed by any other rating the workflows compare against.
+    pub fn dimensions(&self) -> Vec<Rating> {
+        let mut dimensions: Vec<Rating> = Vec::new();
+        for (rating, _) in self.parts.iter().flat_map(|part| part.ratings.iter()) {
+            if !dimensions.contains(rating) {
+                dimensions.push(rating.clone());
+            }
+        }
+
+        let mut others = self
+            .workflow_ratings()
+            .filter(|rating| !dimensions.contains(rating))
+            .cloned()
+            .collect::<Vec<_>>();
+        others.sort();
+        others.dedup();
+        dimensions.extend(others);
+
+        dimensions
+    }
+
+    /// A part spanning `default` in every dimension, except for the ratings
+    /// given their own range in `overrides`.
+    pub fn bounds(
+        &self,
+        default: RangeInclusive<u64>,
+        overrides: &[(Rating, RangeInclusive<u64>)],
+    ) -> Part {
+        let mut bounds = Part {
+            ratings: self
+                .dimensions()
+                .into_iter()
+                .map(|rating| (rating, default.clone()))
+                .collect(),
+        };
+        for (rating, range) in overrides {
+            bounds = bounds.with(rating, range.clone());
+        }
+        bounds
+    }
+
+    /// The ratings the workflows compare against that `part` lacks.
+    pub fn missing_ratings(&self, part: &Part) -> Vec<Rating> {
+        let mut missing = self
+            .workflow_ratings()
+            .filter(|rating| part.try_get(rating).is_none())
+            .cloned()
+            .collect::<Vec<_>>();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+
+    fn workflow_ratings(&self) -> impl Iterator<Item = &Rating> {
+        self.workflows.values().flat_map(|workflow| {
+            workflow
+                .rules
+                .iter()
+                .filter_map(|rule| match rule {
+                    Rule::Normal { cond, .. } => Some(cond.ratings()),
+                    Rule::End(_) => None,
+                })
+                .flatten()
+        })
+    }
+
+    /// Rewrites the workflows into a smaller system that accepts exactly the
+    /// same parts within `bounds`.
+    pub fn optimize(&mut self, bounds: &Part) {
+        loop {
+            let mut changed = false;
+            for workflow in self.workflows.values_mut() {
+                changed |= workflow.prune(bounds);
+            }
+            changed |= self.collapse();
+            changed |= self.inline();
+
+            if !changed {
+                break;
+            }
+        }
+
+        let start = WorkflowName("in".to_owned());
+        let mut reachable = HashSet::new();
+        let mut stack = vec![start];
+        while let Some(name) = stack.pop() {
+            if let Some(workflow) = self.workflows.get(&name) {
+                stack.extend(workflow.targets().cloned());
+                reachable.insert(name);
+            }
+        }
+        self.workflows.retain(|name, _| reachable.contains(name));
+    }
+
+    /// Points every reference to a workflow that always accepts or always
+    /// rejects straight at `A` or `R`.
+    fn collapse(&mut self) -> bool {
+        let constant = self
+            .workflows
+            .iter()
+            .filter(|(name, _)| name.0 != "in")
+            .filter_map(|(name, workflow)| match workflow.rules.as_slice() {
+                [Rule::End(end @ (Transition::Accepted | Transition::Rejected))] => {
+                    Some((name.clone(), end.clone()))
+                }
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut changed = false;
+        for workflow in self.workflows.values_mut() {
+            for rule in workflow.rules.iter_mut() {
+                let result = rule.result_mut();
+                if let Transition::Pointer(name) = result {
+                    if let Some(end) = constant.get(name) {
+                        *result = end.clone();
+                        changed = true;
+                    }
+                }
+            }
+            workflow.sync_end();
+        }
+
+        changed
+    }
+
+    /// Splices a workflow into the workflow that falls back to it, when that
+    /// is its only use. Workflows used as the result of a condition are left
+    /// alone, inlining those would mean `&&`-ing the condition onto every
+    /// inlined rule.
+    fn inline(&mut self) -> bool {
+        let mut uses: HashMap<&WorkflowName, usize> = HashMap::new();
+        for workflow in self.workflows.values() {
+            for target in workflow.targets() {
+                *uses.entry(target).or_default() += 1;
+            }
+        }
+
+        let candidate =
+            self.workflows
+                .iter()
+                .find_map(|(name, workflow)| match workflow.rules.last() {
+                    Some(Rule::End(Transition::Pointer(target)))
+                        if target != name
+                            && target.0 != "in"
+                            && uses.get(target) == Some(&1)
+                            && self.workflows.contains_key(target) =>
+                    {
+                        Some((name.clone(), target.clone()))
+                    }
+                    _ => None,
+                });
+
+        let Some((name, target)) = candidate else {
+            return false;
+        };
+
+        let inlined = self.workflows.remove(&target).unwrap();
+        let workflow = self.workflows.get_mut(&name).unwrap();
+        workflow.rules.pop();
+        workflow.rules.extend(inlined.rules);
+        workflow.sync_end();
+
+        true
+    }
+
+    /// Depth first search that reports every back edge as a cycle. Keeps
+    /// its own stack of the workflows on the current path, along with the
+    /// targets left to visit from each, so long chains of workflows cannot
+    /// overflow the call stack.
+    fn find_cycles<'a>(
+        &'a self,
+        start: &'a WorkflowName,
+        visited: &mut HashSet<&'a WorkflowName>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let mut path: Vec<(&WorkflowName, _)> = Vec::new();
+        let mut positions = HashMap::new();
+        let mut next = Some(start);
+        loop {
+            if let Some(name) = next {
+                if let Some(position) = positions.get(name) {
+                    diagnostics.push(Diagnostic::Cycle(
+                        path[*position..]
+                            .iter()
+                            .map(|(name, _)| (*name).clone())
+                            .collect(),
+                    ));
+                } else if let Some(workflow) = self.workflows.get(name) {
+                    if visited.insert(name) {
+                        positions.insert(name, path.len());
+                        path.push((name, workflow.targets()));
+                    }
+                }
+            }
+
+            let Some((_, targets)) = path.last_mut() else {
+                break;
+            };
+            next = targets.next();
+            if next.is_none() {
+                if let Some((name, _)) = path.pop() {
+                    positions.remove(name);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for System {
+    /// Prints the workflows back in the puzzle syntax, `in` first.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = self.workflows.keys().collect::<Vec<_>>();
+        names.sort_by_key(|name| (name.0 != "in", *name));
+
+        for name in names {
+            let rules = self.workflows[name]
+                .rules
+                .iter()
+                .map(Rule::to_string)
+                .collect::<Vec<_>>();
+            writeln!(f, "{}{{{}}}", name.0, rules.join(","))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::Normal { cond, result } => write!(f, "{cond}:{result}"),
+            Rule::End(result) => write!(f, "{result}"),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Compare { left, comp, right } => write!(f, "{}{comp}{right}", left.0),
+            Condition::All(conds) => {
+                let conds = conds
+                    .iter()
+                    .map(|cond| match cond {
+                        Condition::Any(_) => format!("({cond})"),
+                        _ => cond.to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                write!(f, "{}", conds.join("&&"))
+            }
+            Condition::Any(conds) => {
+                let conds = conds
+                    .iter()
+                    .map(|cond| cond.to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "{}", conds.join("||"))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Comparison::*;
+        let symbol = match self {
+            LessThan => "<",
+            LessOrEqual => "<=",
+            GreaterThan => ">",
+            GreaterOrEqual => ">=",
+            Equal => "==",
+            NotEqual => "!=",
+        };
+        write!(f, "{symbol}")
+    }
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transition::Accepted => write!(f, "A"),
+            Transition::Rejected => write!(f, "R"),
+            Transition::Pointer(name) => write!(f, "{}", name.0),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// There is no `in` workflow to start from.
+    MissingEntry,
+    UndefinedWorkflow {
+        from: WorkflowName,
+        to: WorkflowName,
+    },
+    Unreachable(WorkflowName),
+    /// Workflows that can send a part around in a loop, in order. Only a
+    /// warning, since conditions may keep every part from going round;
+    /// evaluation stops parts that do after a maximum number of hops.
+    Cycle(Vec<WorkflowName>),
+    /// A rule that no part can reach, counting the fallback as the last
+    /// rule.
+    ShadowedRule {
+        workflow: WorkflowName,
+        rule: usize,
+    },
+    /// A rating the workflows compare against that has no bounds.
+    UnboundedRating(Rating),
+    /// A part without a rating that some workflow compares against.
+    MissingRating {
+        part: usize,
+        rating: Rating,
+    },
+}
+
+impl Diagnostic {
+    /// Errors make the system impossible to run, the rest are warnings.
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            Diagnostic::MissingEntry
+                | Diagnostic::UndefinedWorkflow { .. }
+                | Diagnostic::UnboundedRating(_)
+                | Diagnostic::MissingRating { .. }
+        )
+    }
+}
+
+/// Prints the diagnostics to stderr and returns whether any of them keeps
+/// the workflows from being evaluated.
+pub fn report(diagnostics: &[Diagnostic]) -> bool {
+    for diagnostic in diagnostics {
+        let level = if diagnostic.is_error() {
+            "Error"
+        } else {
+            "Warning"
+        };
+        eprintln!("{level}: {diagnostic}");
+    }
+    diagnostics.iter().any(Diagnostic::is_error)
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::MissingEntry => write!(f, "no `in` workflow"),
+            Diagnostic::UndefinedWorkflow { from, to } => {
+                write!(f, "`{}` points to undefined workflow `{}`", from.0, to.0)
+            }
+            Diagnostic::Unreachable(name) => write!(f, "`{}` is unreachable from `in`", name.0),
+            Diagnostic::Cycle(names) => {
+                let names = names.iter().map(|n| n.0.as_str()).collect::<Vec<_>>();
+                write!(f, "cycle {} -> {}", names.join(" -> "), names[0])
+            }
+            Diagnostic::ShadowedRule { workflow, rule } => {
+                write!(f, "rule {} of `{}` can never match", rule + 1, workflow.0)
+            }
+            Diagnostic::UnboundedRating(rating) => {
+                write!(f, "no bounds for rating `{}`", rating.0)
+            }
+            Diagnostic::MissingRating { part, rating } => {
+                write!(f, "part {part} has no `{}` rating", rating.0)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Workflow {
+    pub rules: Vec<Rule>,
+    pub end: Transition,
+}
+
+impl Workflow {
+    fn normalize(&mut self) {
+        self.rules.push(Rule::End(self.end.clone()))
+    }
+
+    /// Drops rules that can never match within `bounds`, everything after a
+    /// rule that always matches, and conditions that lead to the same place
+    /// as the fallback.
+    fn prune(&mut self, bounds: &Part) -> bool {
+        let before = self.rules.len();
+
+        let mut part = Some(vec![bounds.clone()]);
+        let mut kept = Vec::with_capacity(self.rules.len());
+        for rule in self.rules.drain(..) {
+            let Some(current) = part else {
+                break;
+            };
+
+            let (result, _) = rule.execute(&current);
+            match result {
+                ApplyResult::Split { fails, .. } => {
+                    part = Some(fails);
+                    kept.push(rule);
+                }
+                ApplyResult::Passed => {
+                    part = None;
+                    kept.push(Rule::End(rule.result().clone()));
+                }
+                ApplyResult::Failed => part = Some(current),
+            }
+        }
+
+        while kept.len() >= 2 && kept[kept.len() - 2].result() == kept[kept.len() - 1].result() {
+            kept.remove(kept.len() - 2);
+        }
+
+        self.rules = kept;
+        self.sync_end();
+
+        self.rules.len() != before
+    }
+
+    fn sync_end(&mut self) {
+        if let Some(last) = self.rules.last() {
+            self.end = last.result().clone();
+        }
+    }
+
+    pub fn targets(&self) -> impl Iterator<Item = &WorkflowName> {
+        self.rules.iter().filter_map(|rule| match rule {
+            Rule::Normal {
+                result: Transition::Pointer(name),
+                ..
+            }
+            | Rule::End(Transition::Pointer(name)) => Some(name),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum Rule {
+    Normal { cond: Condition, result: Transition },
+    End(Transition),
+}
+
+impl Rule {
+    pub fn result(&self) -> &Transition {
+        match self {
+            Rule::Normal { result, .. } | Rule::End(result) => result,
+        }
+    }
+
+    fn result_mut(&mut self) -> &mut Transition {
+        match self {
+            Rule::Normal { result, .. } | Rule::End(result) => result,
+        }
+    }
+
+    /// Applies the rule to a set of disjoint boxes.
+    pub fn execute(&self, parts: &[Part]) -> (ApplyResult, &Transition) {
+        match self {
+            Rule::Normal { cond, result } => (cond.apply(parts), result),
+            Rule::End(result) => (ApplyResult::Passed, result),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Compare {
+        left: Rating,
+        comp: Comparison,
+        right: u64,
+    },
+    /// Holds when every condition holds, written `a&&b`.
+    All(Vec<Condition>),
+    /// Holds when any condition holds, written `a||b`.
+    Any(Vec<Condition>),
+}
+
+/// How a set of disjoint boxes fares against a condition. The boxes of a
+/// split are disjoint and cover exactly the boxes it started from.
+pub enum ApplyResult {
+    Split { pass: Vec<Part>, fails: Vec<Part> },
+    Passed,
+    Failed,
+}
+
+impl Condition {
+    pub fn ratings(&self) -> Vec<&Rating> {
+        match self {
+            Condition::Compare { left, .. } => vec![left],
+            Condition::All(conds) | Condition::Any(conds) => {
+                conds.iter().flat_map(Condition::ratings).collect()
+            }
+        }
+    }
+
+    pub fn apply(&self, parts: &[Part]) -> ApplyResult {
+        let mut pass = Vec::new();
+        let mut fails = Vec::new();
+        for part in parts {
+            let (p, f) = self.split(part);
+            pass.extend(p);
+            fails.extend(f);
+        }
+
+        if fails.is_empty() {
+            ApplyResult::Passed
+        } else if pass.is_empty() {
+            ApplyResult::Failed
+        } else {
+            ApplyResult::Split { pass, fails }
+        }
+    }
+
+    /// Splits `part` into the disjoint boxes that pass and the ones that
+    /// fail, leaving out empty boxes.
+    pub fn split(&self, part: &Part) -> (Vec<Part>, Vec<Part>) {
+        match self {
+            Condition::Compare { left, comp, right } => {
+                let (pass, fails) = comp.split(part.get(left), *right);
+                let with = |range| part.with(left, range);
+                (
+                    pass.into_iter().map(with).collect(),
+                    fails.into_iter().map(with).collect(),
+                )
+            }
+            Condition::All(conds) => split_all(conds, part, Condition::split),
+            Condition::Any(conds) => split_any(conds, part, Condition::split),
+        }
+    }
+}
+
+/// Splits `start` on every condition in turn, passing what passes all of
+/// them. Shared by conditions on named boxes and compiled ones.
+fn split_all<C, B: Clone>(
+    conds: &[C],
+    start: &B,
+    split: impl Fn(&C, &B) -> (Vec<B>, Vec<B>),
+) -> (Vec<B>, Vec<B>) {
+    let mut pass = vec![start.clone()];
+    let mut fails = Vec::new();
+    for cond in conds {
+        let mut next = Vec::new();
+        for part in pass.iter() {
+            let (p, f) = split(cond, part);
+            next.extend(p);
+            fails.extend(f);
+        }
+        pass = next;
+    }
+    (pass, fails)
+}
+
+/// Splits `start` on every condition in turn, passing what passes any of
+/// them.
+fn split_any<C, B: Clone>(
+    conds: &[C],
+    start: &B,
+    split: impl Fn(&C, &B) -> (Vec<B>, Vec<B>),
+) -> (Vec<B>, Vec<B>) {
+    let mut pass = Vec::new();
+    let mut fails = vec![start.clone()];
+    for cond in conds {
+        let mut next = Vec::new();
+        for part in fails.iter() {
+            let (p, f) = split(cond, part);
+            pass.extend(p);
+            next.extend(f);
+        }
+        fails = next;
+    }
+    (pass, fails)
+}
+
+/// How many workflows a part may pass through before evaluation gives up
+/// on it, unless configured otherwise.
+pub const MAX_HOPS: usize = 1000;
+
+/// A part that was still being sent between workflows after the maximum
+/// number of hops, most likely because the workflows loop.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CycleError {
+    pub part: Part,
+    pub workflow: WorkflowName,
+    pub max_hops: usize,
+}
+
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is still in `{}` after {} hops, the workflows loop",
+            self.part, self.workflow.0, self.max_hops
+        )
+    }
+}
+
+impl std::error::Error for CycleError {}
+
+/// Why the accepted combinations could not be counted.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CountError {
+    Cycle(CycleError),
+    /// More combinations than fit in a `u128`, from bounds too wide to
+    /// count.
+    Overflow,
+}
+
+impl fmt::Display for CountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CountError::Cycle(error) => write!(f, "{error}"),
+            CountError::Overflow => write!(f, "too many combinations to count"),
+        }
+    }
+}
+
+impl std::error::Error for CountError {}
+
+impl From<CycleError> for CountError {
+    fn from(error: CycleError) -> Self {
+        CountError::Cycle(error)
+    }
+}
+
+pub fn process_part(
+    part: Part,
+    workflows: &Workflows,
+    next_transition: &Transition,
+    max_hops: usize,
+) -> Result<u128, CountError> {
+    count_combinations(&accepted_regions(
+        part,
+        workflows,
+        next_transition,
+        max_hops,
+    )?)
+}
+
+/// The combinations in all of `regions` together.
+pub fn count_combinations(regions: &[Region]) -> Result<u128, CountError> {
+    regions
+        .iter()
+        .try_fold(0u128, |total, region| {
+            total.checked_add(region.combinations()?)
+        })
+        .ok_or(CountError::Overflow)
+}
+
+/// A box of accepted ratings and the workflows that led to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub part: Part,
+    pub path: Vec<Hop>,
+}
+
+/// A workflow a region went through, and the condition that sent it on or
+/// `None` for the fallback.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hop {
+    pub workflow: WorkflowName,
+    pub cond: Option<Condition>,
+}
+
+impl Region {
+    /// The number of parts in the region, or `None` if there are more than
+    /// fit in a `u128`.
+    pub fn combinations(&self) -> Option<u128> {
+        self.part
+            .ratings
+            .iter()
+            .try_fold(1u128, |total, (_, range)| {
+                total.checked_mul(u128::from(range.end() - range.start()) + 1)
+            })
+    }
+
+    pub fn path(&self) -> String {
+        self.path
+            .iter()
+            .map(|hop| match &hop.cond {
+                Some(cond) => format!("{} ({cond})", hop.workflow.0),
+                None => hop.workflow.0.clone(),
+            })
+            .chain(["A".to_owned()])
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+}
+
+/// The disjoint boxes of `part` that end up accepted, in the order a
+/// depth first walk through the workflows finds them. Fails once a box has
+/// gone through more than `max_hops` workflows.
+pub fn accepted_regions(
+    part: Part,
+    workflows: &Workflows,
+    next_transition: &Transition,
+    max_hops: usize,
+) -> Result<Vec<Region>, CycleError> {
+    use ApplyResult::*;
+    use Transition::*;
+
+    let mut regions = Vec::new();
+    let mut work = vec![(part, next_transition.clone(), Vec::new())];
+    while let Some((part, transition, path)) = work.pop() {
+        let name = match transition {
+            Pointer(name) => name,
+            Accepted => {
+                regions.push(Region { part, path });
+                continue;
+            }
+            Rejected => continue,
+        };
+        if path.len() >= max_hops {
+            return Err(CycleError {
+                part,
+                workflow: name,
+                max_hops,
+            });
+        }
+
+        let mut next = Vec::new();
+        let mut current = vec![part];
+        for rule in workflows[&name].rules.iter() {
+            let mut path = path.clone();
+            path.push(Hop {
+                workflow: name.clone(),
+                cond: match rule {
+                    Rule::Normal { cond, .. } => Some(cond.clone()),
+                    Rule::End(_) => None,
+                },
+            });
+
+            match rule.execute(&current) {
+                (Split { pass, fails }, transition) => {
+                    next.extend(
+                        pass.into_iter()
+                            .map(|p| (p, transition.clone(), path.clone())),
+                    );
+                    current = fails;
+                }
+                (Passed, transition) => {
+                    next.extend(
+                        current
+                            .into_iter()
+                            .map(|p| (p, transition.clone(), path.clone())),
+                    );
+                    break;
+                }
+                (Failed, _) => {}
+            }
+        }
+
+        // Popping from the end, so push in reverse to visit in rule order.
+        work.extend(next.into_iter().rev());
+    }
+
+    Ok(regions)
+}
+
+/// The workflows a part went through and the transition it ended with.
+#[derive(Debug)]
+pub struct Trace {
+    pub steps: Vec<Step>,
+    pub result: Transition,
+}
+
+#[derive(Debug)]
+pub struct Step {
+    pub workflow: WorkflowName,
+    /// Every condition checked in this workflow and whether it held.
+    pub evaluated: Vec<(Condition, bool)>,
+    /// The condition that sent the part on, or `None` for the fallback.
+    pub matched: Option<Condition>,
+}
+
+impl fmt::Display for Trace {
+    /// Formats the trace as `in -> px (a<2006) -> qkq -> A`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in self.steps.iter() {
+            write!(f, "{}", step.workflow.0)?;
+            if let Some(cond) = &step.matched {
+                write!(f, " ({cond})")?;
+            }
+            write!(f, " -> ")?;
+        }
+        write!(f, "{}", self.result)
+    }
+}
+
+impl System {
+    /// Follows a single part from `in` to the end, which always goes one
+    /// way since a box holding one value per rating never splits.
+    ///
+    /// Panics if `part` holds more than one value for some rating.
+    pub fn trace(&self, part: &Part, max_hops: usize) -> Result<Trace, CycleError> {
+        assert!(part.is_single(), "can only trace a single part");
+        let parts = [part.clone()];
+
+        let mut steps = Vec::new();
+        let mut next = Transition::Pointer(WorkflowName("in".to_owned()));
+        while let Transition::Pointer(name) = next.clone() {
+            if steps.len() >= max_hops {
+                return Err(CycleError {
+                    part: part.clone(),
+                    workflow: name,
+                    max_hops,
+                });
+            }
+
+            let mut step = Step {
+                workflow: name.clone(),
+                evaluated: Vec::new(),
+                matched: None,
+            };
+
+            for rule in self.workflows[&name].rules.iter() {
+                let (result, transition) = rule.execute(&parts);
+                let passed = matches!(result, ApplyResult::Passed);
+                if let Rule::Normal { cond, .. } = rule {
+                    step.evaluated.push((cond.clone(), passed));
+                    if passed {
+                        step.matched = Some(cond.clone());
+                    }
+                }
+                if passed {
+                    next = transition.clone();
+                    break;
+                }
+            }
+
+            steps.push(step);
+        }
+
+        Ok(Trace {
+            steps,
+            result: next,
+        })
+    }
+}
+
+/// Writes one line per region with the bounds of every rating in
+/// `dimensions`, the number of combinations and the path.
+pub fn regions_to_csv(dimensions: &[Rating], regions: &[Region]) -> Result<String, CountError> {
+    let mut out = dimensions
+        .iter()
+        .map(|rating| format!("{0}_min,{0}_max,", rating.0))
+        .collect::<String>();
+    out.push_str("combinations,path\n");
+
+    for region in regions {
+        for rating in dimensions {
+            let range = region.part.get(rating);
+            out.push_str(&format!("{},{},", range.start(), range.end()));
+        }
+        out.push_str(&format!(
+            "{},\"{}\"\n",
+            region.combinations().ok_or(CountError::Overflow)?,
+            region.path()
+        ));
+    }
+    Ok(out)
+}
+
+pub fn regions_to_json(dimensions: &[Rating], regions: &[Region]) -> Result<String, CountError> {
+    let regions = regions
+        .iter()
+        .map(|region| {
+            let ratings = dimensions
+                .iter()
+                .map(|rating| {
+                    let range = region.part.get(rating);
+                    format!("\"{}\": [{}, {}], ", rating.0, range.start(), range.end())
+                })
+                .collect::<String>();
+            Ok(format!(
+                "  {{{ratings}\"combinations\": {}, \"path\": \"{}\"}}",
+                region.combinations().ok_or(CountError::Overflow)?,
+                region.path(),
+            ))
+        })
+        .collect::<Result<Vec<_>, CountError>>()?;
+
+    Ok(format!("[\n{}\n]\n", regions.join(",\n")))
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct Rating(pub String);
+
+impl Part {
+    pub fn try_get(&self, rating: &Rating) -> Option<&RangeInclusive<u64>> {
+        self.ratings
+            .iter()
+            .find(|(name, _)| name == rating)
+            .map(|(_, range)| range)
+    }
+
+    /// Panics for ratings outside the bounds, which `System::validate`
+    /// reports up front.
+    pub fn get(&self, rating: &Rating) -> &RangeInclusive<u64> {
+        self.try_get(rating)
+            .unwrap_or_else(|| panic!("no bounds for rating `{}`", rating.0))
+    }
+
+    /// Whether the box holds a single part.
+    pub fn is_single(&self) -> bool {
+        self.ratings
+            .iter()
+            .all(|(_, range)| range.start() == range.end())
+    }
+
+    /// A copy with `rating` set to `range`, adding the rating at the end
+    /// if it is new.
+    pub fn with(&self, rating: &Rating, range: RangeInclusive<u64>) -> Self {
+        let mut new = self.clone();
+
+        match new.ratings.iter_mut().find(|(name, _)| name == rating) {
+            Some((_, current)) => *current = range,
+            None => new.ratings.push((rating.clone(), range)),
+        }
+
+        new
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    LessThan,
+    LessOrEqual,
+    GreaterThan,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+impl Comparison {
+    /// The comparison that holds exactly when this one does not.
+    fn negate(&self) -> Self {
+        use Comparison::*;
+        match self {
+            LessThan => GreaterOrEqual,
+            LessOrEqual => GreaterThan,
+            GreaterThan => LessOrEqual,
+            GreaterOrEqual => LessThan,
+            Equal => NotEqual,
+            NotEqual => Equal,
+        }
+    }
+
+    /// The ranges of values that compare true against `right`.
+    fn passing(&self, right: u64) -> Vec<RangeInclusive<u64>> {
+        use Comparison::*;
+        let below = right.checked_sub(1).map(|end| 0..=end);
+        let above = right.checked_add(1).map(|start| start..=u64::MAX);
+        match self {
+            LessThan => below.into_iter().collect(),
+            LessOrEqual => vec![0..=right],
+            GreaterThan => above.into_iter().collect(),
+            GreaterOrEqual => vec![right..=u64::MAX],
+            Equal => vec![right..=right],
+            NotEqual => below.into_iter().chain(above).collect(),
+        }
+    }
+
+    /// Splits `range` into the ranges that compare true against `right` and
+    /// the ones that do not, leaving out empty ranges.
+    pub fn split(
+        &self,
+        range: &RangeInclusive<u64>,
+        right: u64,
+    ) -> (Vec<RangeInclusive<u64>>, Vec<RangeInclusive<u64>>) {
+        let clip = |comp: Comparison| {
+            comp.passing(right)
+                .into_iter()
+                .filter_map(|passing| {
+                    let start = *passing.start().max(range.start());
+                    let end = *passing.end().min(range.end());
+                    (start <= end).then_some(start..=end)
+                })
+                .collect()
+        };
+        (clip(*self), clip(self.negate()))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Transition {
+    Pointer(WorkflowName),
+    Rejected,
+    Accepted,
+}
+
+/// A box of parts, with a range for every rating in order.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Part {
+    pub ratings: Vec<(Rating, RangeInclusive<u64>)>,
+}
+
+impl fmt::Display for Part {
+    /// Formats a single part as `{x=787,m=2655}` and any other box as
+    /// `{x=1..=4000,m=2655}`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ratings = self
+            .ratings
+            .iter()
+            .map(|(rating, range)| {
+                if range.start() == range.end() {
+                    format!("{}={}", rating.0, range.start())
+                } else {
+                    format!("{}={}..={}", rating.0, range.start(), range.end())
+                }
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{{{}}}", ratings.join(","))
+    }
+}
+
+/// Parses the workflows and the part list after them, which may be left
+/// out to stream the parts in separately.
+pub fn parse_system(input: &str) -> IResult<&str, System> {
+    map(
+        pair(
+            parse_workflows,
+            opt(preceded(
+                pair(line_ending, line_ending),
+                separated_list0(line_ending, parse_part),
+            )),
+        ),
+        |(workflows, parts)| System {
+            workflows,
+            parts: parts.unwrap_or_default(),
+        },
+    )(input)
+}
+
+/// Splits off the workflows, leaving the part list after the blank line,
+/// with the same line endings `parse_system` accepts.
+pub fn split_system(input: &str) -> IResult<&str, &str> {
+    terminated(recognize(parse_workflows), pair(line_ending, line_ending))(input)
+}
+
+/// Parses `{x=787,m=2655}` into a box holding just that part.
+pub fn parse_part(input: &str) -> IResult<&str, Part> {
+    delimited(
+        complete::char('{'),
+        map(
+            separated_list1(
+                complete::char(','),
+                map(
+                    separated_pair(parse_rating, complete::char('='), complete::u64),
+                    |(rating, value)| (rating, value..=value),
+                ),
+            ),
+            |ratings| Part { ratings },
+        ),
+        complete::char('}'),
+    )(input)
+}
+
+fn parse_workflows(input: &str) -> IResult<&str, Workflows> {
+    map(separated_list1(line_ending, parse_workflow), |workflows| {
+        workflows.into_iter().collect()
+    })(input)
+}
+
+fn parse_workflow(input: &str) -> IResult<&str, (WorkflowName, Workflow)> {
+    pair(
+        map(alpha1, |n: &str| WorkflowName(n.to_owned())),
+        delimited(
+            complete::char('{'),
+            map(pair(parse_rules, parse_transition), |(rules, end)| {
+                let mut w = Workflow { rules, end };
+                w.normalize();
+                w
+            }),
+            complete::char('}'),
+        ),
+    )(input)
+}
+
+fn parse_rules(input: &str) -> IResult<&str, Vec<Rule>> {
+    many0(terminated(parse_rule, complete::char(',')))(input)
+}
+
+fn parse_rule(input: &str) -> IResult<&str, Rule> {
+    map(
+        separated_pair(parse_cond, complete::char(':'), parse_transition),
+        |(cond, result)| Rule::Normal { cond, result },
+    )(input)
+}
+
+/// Parses `||` separated groups of `&&` separated comparisons, so `&&`
+/// binds tighter. Parentheses group explicitly.
+fn parse_cond(input: &str) -> IResult<&str, Condition> {
+    map(separated_list1(tag("||"), parse_all), |mut conds| {
+        if conds.len() == 1 {
+            conds.remove(0)
+        } else {
+            Condition::Any(conds)
+        }
+    })(input)
+}
+
+fn parse_all(input: &str) -> IResult<&str, Condition> {
+    map(
+        separated_list1(
+            tag("&&"),
+            alt((
+                parse_compare,
+                delimited(complete::char('('), parse_cond, complete::char(')')),
+            )),
+        ),
+        |mut conds| {
+            if conds.len() == 1 {
+                conds.remove(0)
+            } else {
+                Condition::All(conds)
+            }
+        },
+    )(input)
+}
+
+fn parse_compare(input: &str) -> IResult<&str, Condition> {
+    map(
+        tuple((parse_rating, parse_comparison, complete::u64)),
+        |(left, comp, right)| Condition::Compare { left, comp, right },
+    )(input)
+}
+
+fn parse_comparison(input: &str) -> IResult<&str, Comparison> {
+    use Comparison::*;
+    alt((
+        map(tag("<="), |_| LessOrEqual),
+        map(tag(">="), |_| GreaterOrEqual),
+        map(tag("=="), |_| Equal),
+        map(tag("!="), |_| NotEqual),
+        map(tag("<"), |_| LessThan),
+        map(tag(">"), |_| GreaterThan),
+    ))(input)
+}
+
+fn parse_rating(input: &str) -> IResult<&str, Rating> {
+    map(alpha1, |n: &str| Rating(n.to_owned()))(input)
+}
+
+/// Parses `lo..=hi`.
+/// Parses `lo..=hi`, rejecting empty ranges with `lo` above `hi`.
+fn parse_range(input: &str) -> IResult<&str, RangeInclusive<u64>> {
+    map(
+        verify(
+            separated_pair(complete::u64, tag("..="), complete::u64),
+            |(lo, hi)| lo <= hi,
+        ),
+        |(lo, hi)| lo..=hi,
+    )(input)
+}
+
+/// The range for every rating, if given, and the ranges for single ratings.
+pub type Bounds = (
+    Option<RangeInclusive<u64>>,
+    Vec<(Rating, RangeInclusive<u64>)>,
+);
+
+/// Parses the `--bounds` argument, a comma separated list of `lo..=hi` for
+/// every rating and `name=lo..=hi` for a single one.
+pub fn parse_bounds(input: &str) -> IResult<&str, Bounds> {
+    map(
+        separated_list1(
+            complete::char(','),
+            alt((
+                map(parse_range, |range| (None, range)),
+                map(
+                    separated_pair(parse_rating, complete::char('='), parse_range),
+                    |(rating, range)| (Some(rating), range),
+                ),
+            )),
+        ),
+        |entries| {
+            let mut default = None;
+            let mut overrides = Vec::new();
+            for (rating, range) in entries {
+                match rating {
+                    Some(rating) => overrides.push((rating, range)),
+                    None => default = Some(range),
+                }
+            }
+            (default, overrides)
+        },
+    )(input)
+}
+
+fn parse_transition(input: &str) -> IResult<&str, Transition> {
+    use Transition::*;
+    alt((
+        map(tag("A"), |_| Accepted),
+        map(tag("R"), |_| Rejected),
+        map(alpha1, |n: &str| Pointer(WorkflowName(n.to_owned()))),
+    ))(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        accepted_regions, count_combinations, parse_bounds, parse_system, process_part,
+        regions_to_csv, regions_to_json, CountError, Diagnostic, Part, Rating, System, Transition,
+        WorkflowName, MAX_HOPS,
+    };
+
+    fn rating(name: &str) -> Rating {
+        Rating(name.to_owned())
+    }
+
+    fn bounds() -> Part {
+        Part {
+            ratings: ["x", "m", "a", "s"]
+                .into_iter()
+                .map(|name| (rating(name), 1..=4000))
+                .collect(),
+        }
+    }
+
+    fn name(name: &str) -> WorkflowName {
+        WorkflowName(name.to_owned())
+    }
+
+    #[test]
+    fn example() {
+        let (_, system) = parse_system(include_str!("bin/test.txt")).expect("failed to parse");
+        let answer = process_part(
+            bounds(),
+            &system.workflows,
+            &Transition::Pointer(name("in")),
+            MAX_HOPS,
+        )
+        .unwrap();
+        assert_eq!(answer, 167409079868000);
+    }
+
+    #[test]
+    fn example_is_valid() {
+        let (_, system) = parse_system(include_str!("bin/test.txt")).expect("failed to parse");
+        assert_eq!(system.validate(&bounds()), vec![]);
+    }
+
+    #[test]
+    fn long_chains() {
+        // Workflow names are letters only, so spell the numbers in base 26.
+        let name = |mut i: usize| {
+            let mut name = "w".to_owned();
+            for _ in 0..4 {
+                name.push((b'a' + (i % 26) as u8) as char);
+                i /= 26;
+            }
+            name
+        };
+        let chain = (0..100_000)
+            .map(|i| format!("{}{{x<10:{},A}}", name(i), name(i + 1)))
+            .chain([
+                format!("in{{x<10:{},A}}", name(0)),
+                format!("{}{{x<10:in,A}}", name(100_000)),
+            ])
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (_, system) = parse_system(&format!("{chain}\n\n")).expect("failed to parse");
+
+        let diagnostics = system.validate(&bounds());
+        assert_eq!(diagnostics.len(), 1);
+        let Diagnostic::Cycle(cycle) = &diagnostics[0] else {
+            panic!("expected a cycle, got {diagnostics:?}");
+        };
+        assert_eq!(cycle.len(), 100_002);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let (_, system) = parse_system(
+            "in{x<10:px,qq}\npx{x<5000:A,R}\nqq{a<5:px,m>4000:R,qq}\nzz{s<1:A,s<2000:A,A}\n\n",
+        )
+        .expect("failed to parse");
+
+        assert_eq!(
+            system.validate(&bounds()),
+            vec![
+                Diagnostic::Unreachable(name("zz")),
+                Diagnostic::Cycle(vec![name("qq")]),
+                Diagnostic::ShadowedRule {
+                    workflow: name("px"),
+                    rule: 1
+                },
+                Diagnostic::ShadowedRule {
+                    workflow: name("qq"),
+                    rule: 1
+                },
+                Diagnostic::ShadowedRule {
+                    workflow: name("zz"),
+                    rule: 0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn undefined_workflows() {
+        let (_, system) = parse_system("px{x<10:qq,A}\n\n").expect("failed to parse");
+        let diagnostics = system.validate(&bounds());
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::MissingEntry,
+                Diagnostic::UndefinedWorkflow {
+                    from: name("px"),
+                    to: name("qq")
+                },
+                Diagnostic::Unreachable(name("px")),
+            ]
+        );
+        assert!(diagnostics[0].is_error());
+        assert!(diagnostics[1].is_error());
+        assert!(!diagnostics[2].is_error());
+    }
+
+    fn count(system: &System) -> u128 {
+        process_part(
+            bounds(),
+            &system.workflows,
+            &Transition::Pointer(name("in")),
+            MAX_HOPS,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn optimize_keeps_accepted_combinations() {
+        for input in [include_str!("bin/test.txt"), include_str!("bin/input.txt")] {
+            let (_, mut system) = parse_system(input).expect("failed to parse");
+            let expected = count(&system);
+            let workflows = system.workflows.len();
+
+            system.optimize(&bounds());
+            assert!(system.workflows.len() < workflows);
+            assert_eq!(count(&system), expected);
+
+            let printed = format!("{system}\n");
+            let (_, reparsed) = parse_system(&printed).expect("failed to parse printed system");
+            assert_eq!(count(&reparsed), expected);
+            assert_eq!(format!("{reparsed}"), format!("{system}"));
+        }
+    }
+
+    #[test]
+    fn optimize_example() {
+        let (_, mut system) = parse_system(
+            "in{s<1351:px,x>5000:R,qqz}\npx{a<2006:A,lnx}\nlnx{m>1548:A,A}\nqqz{s>2770:R,gd}\ngd{a>3333:R,R}\n\n",
+        )
+        .expect("failed to parse");
+
+        system.optimize(&bounds());
+        assert_eq!(format!("{system}"), "in{s<1351:A,R}\n");
+    }
+
+    #[test]
+    fn regions() {
+        let (_, system) = parse_system(include_str!("bin/test.txt")).expect("failed to parse");
+        let regions = accepted_regions(
+            bounds(),
+            &system.workflows,
+            &Transition::Pointer(name("in")),
+            MAX_HOPS,
+        )
+        .unwrap();
+
+        assert_eq!(count_combinations(&regions), Ok(167409079868000));
+
+        for (i, a) in regions.iter().enumerate() {
+            for b in regions[i + 1..].iter() {
+                let overlap = |x: &std::ops::RangeInclusive<u64>,
+                               y: &std::ops::RangeInclusive<u64>| {
+                    x.start().max(y.start()) <= x.end().min(y.end())
+                };
+                assert!(
+                    !a.part
+                        .ratings
+                        .iter()
+                        .zip(b.part.ratings.iter())
+                        .all(|((_, x), (_, y))| overlap(x, y)),
+                    "{a:?} overlaps {b:?}"
+                );
+            }
+        }
+
+        let first = &regions[0];
+        assert_eq!(
+            first.path(),
+            "in (s<1351) -> px (a<2006) -> qkq (x<1416) -> A"
+        );
+        assert_eq!(
+            first.part,
+            bounds()
+                .with(&rating("x"), 1..=1415)
+                .with(&rating("a"), 1..=2005)
+                .with(&rating("s"), 1..=1350)
+        );
+
+        let dimensions = ["x", "m", "a", "s"].map(rating);
+        let csv = regions_to_csv(&dimensions, &regions).unwrap();
+        assert_eq!(csv.lines().count(), regions.len() + 1);
+        assert_eq!(
+            csv.lines().nth(1),
+            Some("1,1415,1,4000,1,2005,1,1350,15320205000000,\"in (s<1351) -> px (a<2006) -> qkq (x<1416) -> A\"")
+        );
+
+        let json = regions_to_json(&dimensions, &regions).unwrap();
+        assert!(json.starts_with("[\n  {\"x\": [1, 1415], \"m\": [1, 4000]"));
+        assert_eq!(json.matches("\"path\"").count(), regions.len());
+    }
+
+    #[test]
+    fn wide_bounds() {
+        assert!(parse_bounds("10..=1").is_err());
+        assert_eq!(parse_bounds("1..=10,x=5..=4").unwrap().0, ",x=5..=4");
+
+        let (_, system) = parse_system(include_str!("bin/test.txt")).expect("failed to parse");
+        let count = |bounds| {
+            process_part(
+                bounds,
+                &system.workflows,
+                &Transition::Pointer(name("in")),
+                MAX_HOPS,
+            )
+        };
+
+        let (_, (default, overrides)) = parse_bounds("1..=1000000").unwrap();
+        let bounds = system.bounds(default.unwrap(), &overrides);
+        assert!(count(bounds).unwrap() > u128::from(u64::MAX));
+
+        let bounds = system.bounds(0..=u64::MAX, &[]);
+        assert_eq!(count(bounds.clone()), Err(CountError::Overflow));
+        let regions = accepted_regions(
+            bounds,
+            &system.workflows,
+            &Transition::Pointer(name("in")),
+            MAX_HOPS,
+        )
+        .unwrap();
+        assert_eq!(
+            regions_to_csv(&system.dimensions(), &regions),
+            Err(CountError::Overflow)
+        );
+    }
+
+    #[test]
+    fn dimensions() {
+        let (_, system) = parse_system(include_str!("bin/test.txt")).expect("failed to parse");
+        assert_eq!(system.dimensions(), ["x", "m", "a", "s"].map(rating));
+        assert_eq!(system.bounds(1..=4000, &[]), bounds());
+
+        let (_, system) =
+            parse_system("in{weight>10:A,colour<3:A,R}\n\n{size=1,colour=2,weight=3}\n")
+                .expect("failed to parse");
+        assert_eq!(
+            system.dimensions(),
+            ["size", "colour", "weight"].map(rating)
+        );
+
+        let (_, (default, overrides)) = parse_bounds("1..=10,weight=1..=20").unwrap();
+        let bounds = system.bounds(default.unwrap(), &overrides);
+        assert_eq!(system.validate(&bounds), vec![]);
+        // size is free, weight>10 accepts 10 and colour<3 accepts 2 of the
+        // remaining 10 weights.
+        assert_eq!(
+            process_part(
+                bounds,
+                &system.workflows,
+                &Transition::Pointer(name("in")),
+                MAX_HOPS
+            )
+            .unwrap(),
+            10 * (10 * 10 + 2 * 10)
+        );
+
+        let bounds = Part {
+            ratings: vec![(rating("weight"), 1..=20)],
+        };
+        assert_eq!(
+            system.validate(&bounds),
+            vec![Diagnostic::UnboundedRating(rating("colour"))]
+        );
+    }
+
+    #[test]
+    fn compound_conditions_split_exactly() {
+        let (_, system) =
+            parse_system("in{x<=3&&m!=2:px,a==4||s>=5&&(x==1||m==6):A,R}\npx{s!=3||a<2:A,R}\n\n")
+                .expect("failed to parse");
+        assert_eq!(
+            format!("{system}"),
+            "in{x<=3&&m!=2:px,a==4||s>=5&&(x==1||m==6):A,R}\npx{s!=3||a<2:A,R}\n"
+        );
+
+        let accepts = |x: u64, m: u64, a: u64, s: u64| {
+            if x <= 3 && m != 2 {
+                s != 3 || a < 2
+            } else {
+                a == 4 || s >= 5 && (x == 1 || m == 6)
+            }
+        };
+        let values = 1..=6;
+        let mut expected = 0;
+        for x in values.clone() {
+            for m in values.clone() {
+                for a in values.clone() {
+                    for s in values.clone() {
+                        expected += accepts(x, m, a, s) as u64;
+                    }
+                }
+            }
+        }
+
+        let bounds = system.bounds(values, &[]);
+        let regions = accepted_regions(
+            bounds.clone(),
+            &system.workflows,
+            &Transition::Pointer(name("in")),
+            MAX_HOPS,
+        )
+        .unwrap();
+        assert_eq!(count_combinations(&regions), Ok(u128::from(expected)));
+        assert_eq!(system.validate(&bounds), vec![]);
+
+        let program = system.compile().expect("failed to compile workflows");
+        let accepted = program
+            .accepted(program.ranges(&bounds).unwrap(), MAX_HOPS)
+            .unwrap();
+        assert_eq!(accepted.len(), regions.len());
+        assert_eq!(
+            accepted
+                .iter()
+                .map(|ranges| ranges
+                    .iter()
+                    .map(|range| range.end() - range.start() + 1)
+                    .product::<u64>())
+                .sum::<u64>(),
+            expected
+        );
+    }
+
+    /// Part 1 runs every part through the compiled workflows on its own,
+    /// part 2 splits the whole bounds into regions. A part should be
+    /// accepted exactly when it lies in an accepted region, and its trace
+    /// should agree.
+    #[test]
+    fn parts_agree_with_regions() {
+        let (_, system) = parse_system(include_str!("bin/test.txt")).expect("failed to parse");
+        let program = system.compile().expect("failed to compile workflows");
+        let regions = accepted_regions(
+            system.bounds(1..=4000, &[]),
+            &system.workflows,
+            &Transition::Pointer(name("in")),
+            MAX_HOPS,
+        )
+        .unwrap();
+
+        for part in system.parts.iter() {
+            let containing = regions
+                .iter()
+                .filter(|region| {
+                    part.ratings
+                        .iter()
+                        .all(|(rating, value)| region.part.get(rating).contains(value.start()))
+                })
+                .count();
+            assert!(containing <= 1, "{part} lies in overlapping regions");
+
+            let accepted = program.accepts(part, MAX_HOPS).unwrap();
+            assert_eq!(accepted, containing == 1, "{part}");
+            assert_eq!(
+                system.trace(part, MAX_HOPS).unwrap().result == Transition::Accepted,
+                accepted,
+                "{part}"
+            );
+        }
+    }
+
+    #[test]
+    fn cycles_stop_after_max_hops() {
+        let (_, system) =
+            parse_system("in{x<10:lp,R}\nlp{x<5:in,A}\n\n{x=1}\n{x=7}").expect("failed to parse");
+        let diagnostics = system.validate(&system.bounds(1..=4000, &[]));
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::Cycle(vec![name("in"), name("lp")])]
+        );
+        assert!(!diagnostics[0].is_error());
+
+        let program = system.compile().expect("failed to compile workflows");
+        assert_eq!(program.accepts(&system.parts[1], 50), Ok(true));
+        let error = program.accepts(&system.parts[0], 50).unwrap_err();
+        assert_eq!(error.max_hops, 50);
+        assert_eq!(error.part, system.parts[0]);
+        assert!(system.trace(&system.parts[0], 50).is_err());
+        assert_eq!(
+            system.trace(&system.parts[1], 50).unwrap().to_string(),
+            "in (x<10) -> lp -> A"
+        );
+
+        let bounds = system.bounds(1..=4000, &[]);
+        assert!(accepted_regions(
+            bounds,
+            &system.workflows,
+            &Transition::Pointer(name("in")),
+            50
+        )
+        .is_err());
+
+        // The loop back to `in` is only taken by parts `in` never sends on.
+        let (_, system) =
+            parse_system("in{x<10:lp,R}\nlp{x>20:in,A}\n\n").expect("failed to parse");
+        let bounds = system.bounds(1..=4000, &[]);
+        assert_eq!(
+            process_part(
+                bounds,
+                &system.workflows,
+                &Transition::Pointer(name("in")),
+                50
+            ),
+            Ok(9)
+        );
+    }
+}
@@ -0,0 +1,338 @@
+use std::{collections::HashMap, fmt, ops::RangeInclusive};
+
+use crate::{
+    split_all, split_any, Comparison, Condition, CycleError, Part, Rating, Rule, System,
+    Transition, WorkflowName,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CompileError {
+    UndefinedWorkflow(WorkflowName),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::UndefinedWorkflow(WorkflowName(name)) => {
+                write!(f, "workflow `{name}` is not defined")
+            }
+        }
+    }
+}
+
+/// A box of parts with a range for every rating a program reads, in the
+/// order of `Program::ratings`.
+pub type Ranges = Vec<RangeInclusive<u64>>;
+
+/// Workflows compiled to a flat list of ops with integer jumps, reading
+/// ratings by index instead of by name. It splits boxes of parts the same
+/// way `accepted_regions` does, just without the names, and a single part
+/// goes through as a box holding one value per rating.
+#[derive(Debug)]
+pub struct Program {
+    ops: Vec<Op>,
+    /// Where every workflow starts in `ops`, with `in` at 0.
+    starts: Vec<usize>,
+    names: Vec<WorkflowName>,
+    ratings: Vec<Rating>,
+}
+
+#[derive(Debug)]
+enum Op {
+    /// Send the boxes passing the check to `target`, and the rest on to
+    /// the next op.
+    Test {
+        check: Check,
+        target: Target,
+    },
+    Goto(Target),
+}
+
+/// A `Condition` with every rating replaced by its index in `Ranges`.
+#[derive(Debug)]
+enum Check {
+    Compare {
+        rating: usize,
+        comp: Comparison,
+        right: u64,
+    },
+    All(Vec<Check>),
+    Any(Vec<Check>),
+}
+
+impl Check {
+    /// Splits `ranges` into the disjoint boxes that pass and the ones that
+    /// fail, like `Condition::split`.
+    fn split(&self, ranges: &Ranges) -> (Vec<Ranges>, Vec<Ranges>) {
+        match self {
+            Check::Compare {
+                rating,
+                comp,
+                right,
+            } => {
+                let (pass, fails) = comp.split(&ranges[*rating], *right);
+                let with = |range| {
+                    let mut ranges = ranges.clone();
+                    ranges[*rating] = range;
+                    ranges
+                };
+                (
+                    pass.into_iter().map(with).collect(),
+                    fails.into_iter().map(with).collect(),
+                )
+            }
+            Check::All(checks) => split_all(checks, ranges, Check::split),
+            Check::Any(checks) => split_any(checks, ranges, Check::split),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    /// Go to the workflow with this index.
+    Jump(usize),
+    Accept,
+    Reject,
+}
+
+impl System {
+    /// Lays every workflow out in one flat list of ops, starting with `in`,
+    /// so that evaluating parts never looks a workflow or rating up by
+    /// name. Every workflow already ends in a `Rule::End` for its fallback,
+    /// which becomes its last op.
+    pub fn compile(&self) -> Result<Program, CompileError> {
+        let start = WorkflowName("in".to_owned());
+        if !self.workflows.contains_key(&start) {
+            return Err(CompileError::UndefinedWorkflow(start));
+        }
+
+        let mut names = self.workflows.keys().cloned().collect::<Vec<_>>();
+        names.sort_by_key(|name| *name != start);
+        let indices = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (name, index))
+            .collect::<HashMap<_, _>>();
+
+        let mut ratings = self.workflow_ratings().cloned().collect::<Vec<_>>();
+        ratings.sort();
+        ratings.dedup();
+
+        let target = |transition: &Transition| match transition {
+            Transition::Accepted => Ok(Target::Accept),
+            Transition::Rejected => Ok(Target::Reject),
+            Transition::Pointer(name) => indices
+                .get(name)
+                .map(|index| Target::Jump(*index))
+                .ok_or_else(|| CompileError::UndefinedWorkflow(name.clone())),
+        };
+
+        let mut ops = Vec::new();
+        let mut starts = Vec::with_capacity(names.len());
+        for name in names.iter() {
+            starts.push(ops.len());
+            let workflow = &self.workflows[name];
+            for rule in workflow.rules.iter() {
+                ops.push(match rule {
+                    Rule::Normal { cond, result } => Op::Test {
+                        check: check(cond, &ratings),
+                        target: target(result)?,
+                    },
+                    Rule::End(result) => Op::Goto(target(result)?),
+                });
+            }
+        }
+
+        Ok(Program {
+            ops,
+            starts,
+            names,
+            ratings,
+        })
+    }
+}
+
+fn check(cond: &Condition, ratings: &[Rating]) -> Check {
+    match cond {
+        Condition::Compare { left, comp, right } => Check::Compare {
+            rating: ratings.binary_search(left).expect("rating of a workflow"),
+            comp: *comp,
+            right: *right,
+        },
+        Condition::All(conds) => Check::All(conds.iter().map(|c| check(c, ratings)).collect()),
+        Condition::Any(conds) => Check::Any(conds.iter().map(|c| check(c, ratings)).collect()),
+    }
+}
+
+impl Program {
+    /// The ratings the workflows compare against.
+    pub fn ratings(&self) -> &[Rating] {
+        &self.ratings
+    }
+
+    /// The ranges of `part` in the order the program reads them, or the
+    /// first rating it lacks.
+    pub fn ranges(&self, part: &Part) -> Result<Ranges, Rating> {
+        self.ratings
+            .iter()
+            .map(|rating| part.try_get(rating).cloned().ok_or_else(|| rating.clone()))
+            .collect()
+    }
+
+    fn part(&self, ranges: Ranges) -> Part {
+        Part {
+            ratings: self.ratings.iter().cloned().zip(ranges).collect(),
+        }
+    }
+
+    /// The disjoint boxes of `bounds` that end up accepted, in the order a
+    /// depth first walk through the workflows finds them. Fails once a box
+    /// has gone through more than `max_hops` workflows.
+    pub fn accepted(&self, bounds: Ranges, max_hops: usize) -> Result<Vec<Ranges>, CycleError> {
+        let mut accepted = Vec::new();
+        let mut work = vec![(bounds, Target::Jump(0), 0)];
+        while let Some((ranges, target, hops)) = work.pop() {
+            let workflow = match target {
+                Target::Jump(workflow) => workflow,
+                Target::Accept => {
+                    accepted.push(ranges);
+                    continue;
+                }
+                Target::Reject => continue,
+            };
+            if hops >= max_hops {
+                return Err(CycleError {
+                    part: self.part(ranges),
+                    workflow: self.names[workflow].clone(),
+                    max_hops,
+                });
+            }
+
+            let mut next = Vec::new();
+            let mut current = vec![ranges];
+            for op in self.ops[self.starts[workflow]..].iter() {
+                match op {
+                    Op::Test { check, target } => {
+                        let mut fails = Vec::new();
+                        for ranges in current.iter() {
+                            let (p, f) = check.split(ranges);
+                            next.extend(p.into_iter().map(|p| (p, *target, hops + 1)));
+                            fails.extend(f);
+                        }
+                        current = fails;
+                    }
+                    Op::Goto(target) => {
+                        next.extend(current.into_iter().map(|r| (r, *target, hops + 1)));
+                        break;
+                    }
+                }
+            }
+
+            // Popping from the end, so push in reverse to visit in rule order.
+            work.extend(next.into_iter().rev());
+        }
+
+        Ok(accepted)
+    }
+
+    /// Whether a single part ends up accepted.
+    ///
+    /// Panics if `part` lacks a rating the workflows compare against, which
+    /// `System::validate` reports up front.
+    pub fn accepts(&self, part: &Part, max_hops: usize) -> Result<bool, CycleError> {
+        let ranges = self
+            .ranges(part)
+            .unwrap_or_else(|rating| panic!("{part} has no `{}` rating", rating.0));
+        self.accepts_ranges(part, ranges, max_hops)
+    }
+
+    /// Whether `part`, already turned into `ranges`, ends up accepted,
+    /// blaming the whole part rather than just its compared ratings when it
+    /// loops.
+    pub fn accepts_ranges(
+        &self,
+        part: &Part,
+        ranges: Ranges,
+        max_hops: usize,
+    ) -> Result<bool, CycleError> {
+        match self.accepted(ranges, max_hops) {
+            Ok(accepted) => Ok(!accepted.is_empty()),
+            Err(error) => Err(CycleError {
+                part: part.clone(),
+                ..error
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse_system, WorkflowName, MAX_HOPS};
+
+    use super::CompileError;
+
+    #[test]
+    fn example() {
+        let (_, system) = parse_system(include_str!("bin/test.txt")).expect("failed to parse");
+        let program = system.compile().expect("failed to compile workflows");
+        // one op per rule, the fallback included
+        assert_eq!(
+            program.ops.len(),
+            system
+                .workflows
+                .values()
+                .map(|w| w.rules.len())
+                .sum::<usize>()
+        );
+
+        let accepted = system
+            .parts
+            .iter()
+            .map(|part| program.accepts(part, MAX_HOPS).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(accepted, vec![true, false, true, false, true]);
+
+        let bounds = program.ranges(&system.bounds(1..=4000, &[])).unwrap();
+        let combinations = program
+            .accepted(bounds, MAX_HOPS)
+            .unwrap()
+            .iter()
+            .map(|ranges| {
+                ranges
+                    .iter()
+                    .map(|range| range.end() - range.start() + 1)
+                    .product::<u64>()
+            })
+            .sum::<u64>();
+        assert_eq!(combinations, 167409079868000);
+    }
+
+    #[test]
+    fn undefined_workflows() {
+        let (_, system) =
+            parse_system("in{x<10:px,A}\n\n{x=1,m=2,a=3,s=4}").expect("failed to parse");
+        assert_eq!(
+            system.compile().unwrap_err(),
+            CompileError::UndefinedWorkflow(WorkflowName("px".to_owned()))
+        );
+
+        let (_, system) =
+            parse_system("px{x<10:A,R}\n\n{x=1,m=2,a=3,s=4}").expect("failed to parse");
+        assert_eq!(
+            system.compile().unwrap_err(),
+            CompileError::UndefinedWorkflow(WorkflowName("in".to_owned()))
+        );
+    }
+
+    #[test]
+    fn loops_stop_after_max_hops() {
+        let (_, system) =
+            parse_system("in{x<10:lp,R}\nlp{x<5:in,A}\n\n{x=1}\n{x=7}").expect("failed to parse");
+        let program = system.compile().unwrap();
+
+        assert_eq!(program.accepts(&system.parts[1], 50), Ok(true));
+        let error = program.accepts(&system.parts[0], 50).unwrap_err();
+        assert_eq!(error.part, system.parts[0]);
+        assert_eq!(error.max_hops, 50);
+    }
+}
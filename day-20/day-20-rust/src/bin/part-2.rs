@@ -1,220 +1,98 @@
-use std::collections::{HashMap, VecDeque};
-
-use nom::{
-    branch::alt,
-    bytes::complete::tag,
-    character::complete::{self, alpha1, line_ending},
-    combinator::map,
-    multi::separated_list1,
-    sequence::{separated_pair, tuple},
-    IResult,
+use day_20_rust::{
+    cycles::{find_cycles, first_press, CycleError, MAX_PRESSES},
+    parse_modules, report, ModuleName, ModuleType, Pulse, Simulator,
 };
 
-#[derive(Debug, Clone)]
-struct Module {
-    t: ModuleType,
-    dests: Vec<ModuleName>,
-}
-
-#[derive(Debug, Clone)]
-enum ModuleType {
-    FlipFlop(bool),
-    Conjunction(HashMap<ModuleName, Pulse>),
-    Broadcaster,
-}
-
-impl ModuleType {
-    fn flip_flop() -> Self {
-        Self::FlipFlop(false)
-    }
-}
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum Pulse {
-    Low,
-    High,
-}
-
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
-struct ModuleName(String);
-
-#[derive(Debug)]
-struct Modules(ModuleMap);
-type ModuleMap = HashMap<ModuleName, Module>;
-
-impl Modules {
-    fn set_conjunctions(&mut self) {
-        let mut to_conjunction_dests = Vec::new();
-
-        for (name, module) in self.0.iter() {
-            for dest in module.dests.iter() {
-                if let Some(ModuleType::Conjunction(_)) = self.0.get(dest).map(|dest| &dest.t) {
-                    to_conjunction_dests.push((dest.clone(), name.clone()));
-                }
-            }
+/// Works out when every input of the conjunction feeding `rx` sends it a
+/// high pulse on the same press, which is when `rx` gets a low pulse.
+fn find_rx(simulator: &mut Simulator, max_presses: u64) -> Result<u64, CycleError> {
+    let rx = ModuleName::new("rx");
+    let feed = match simulator.modules.inputs(&rx).as_slice() {
+        [] => return Err(CycleError::NoInputs(rx)),
+        [feed] if simulator.modules.map[feed].t == ModuleType::Conjunction => {
+            simulator.id(feed).unwrap()
         }
-
-        for (conjunction, input) in to_conjunction_dests {
-            let conjunction = self.0.get_mut(&conjunction).unwrap();
-            match conjunction.t.clone() {
-                ModuleType::Conjunction(mut memory) => {
-                    memory.insert(input, Pulse::Low);
-                    conjunction.t = ModuleType::Conjunction(memory);
-                }
-                _ => unreachable!(),
-            }
+        [feed] => return Err(CycleError::FeedNotConjunction(feed.clone())),
+        feeders => {
+            return Err(CycleError::MultipleFeeders {
+                target: rx,
+                feeders: feeders.to_vec(),
+            })
         }
+    };
+
+    let cycles = find_cycles(simulator, feed, Pulse::High, max_presses)?;
+    for (input, cycle) in cycles.iter() {
+        eprintln!(
+            "{} first sends high on press {} and then every {} presses",
+            simulator.name(*input).0,
+            cycle.first,
+            cycle.period
+        );
     }
 
-    fn find_rx(&mut self) -> u64 {
-        let (feed_name, feed) = self
-            .0
-            .iter()
-            .find(|(_, module)| module.dests.contains(&ModuleName("rx".to_owned())))
-            .map(|(key, value)| (key.clone(), value.clone()))
-            .unwrap();
-
-        let mut seen = match feed.t.clone() {
-            ModuleType::Conjunction(memory) => memory
-                .into_keys()
-                .map(|key| (key, 0))
-                .collect::<HashMap<ModuleName, u64>>(),
-            _ => unreachable!(),
-        };
-        let mut cycle_lengths: HashMap<ModuleName, u64> = HashMap::new();
-
-        let mut presses = 0;
-        'top: loop {
-            presses += 1;
-
-            let broadcaster = self
-                .0
-                .get(&ModuleName("broadcaster".to_owned()))
-                .cloned()
-                .unwrap();
-
-            let mut q: VecDeque<(ModuleName, ModuleName, Pulse)> = VecDeque::from_iter(
-                broadcaster
-                    .dests
-                    .into_iter()
-                    .map(|dest| (ModuleName("broadcaster".to_owned()), dest, Pulse::Low)),
-            );
-
-            while !q.is_empty() {
-                let (origin, target, pulse) = q.pop_front().unwrap();
-
-                if !self.0.contains_key(&target) {
-                    continue;
-                }
-
-                let module = self.0.get_mut(&target).unwrap();
-
-                if target == feed_name && pulse == Pulse::High {
-                    seen.entry(origin.clone()).and_modify(|c| *c += 1);
-                    cycle_lengths.entry(origin.clone()).or_insert(presses);
-
-                    if seen.values().all(|x| *x > 0) {
-                        let mut min_cycle = 1;
-                        for c in cycle_lengths.into_values() {
-                            min_cycle = lcm(min_cycle, c);
-                        }
-
-                        break 'top min_cycle;
-                    }
-                }
-
-                match module.t.clone() {
-                    ModuleType::FlipFlop(state) => match pulse {
-                        Pulse::Low => {
-                            let new_state = !state;
-                            module.t = ModuleType::FlipFlop(new_state);
-
-                            let new_pulse = if new_state { Pulse::High } else { Pulse::Low };
+    first_press(
+        &cycles
+            .into_iter()
+            .map(|(_, cycle)| cycle)
+            .collect::<Vec<_>>(),
+    )
+}
 
-                            for dest in module.dests.iter() {
-                                q.push_back((target.clone(), dest.clone(), new_pulse.clone()))
-                            }
-                        }
-                        Pulse::High => continue,
-                    },
-                    ModuleType::Conjunction(mut memory) => {
-                        memory.insert(origin, pulse);
+fn main() {
+    let (_, modules) = parse_modules(include_str!("input.txt")).expect("failed to parse");
+    if report(&modules.validate()) {
+        std::process::exit(1);
+    }
 
-                        let new_pulse = if memory.values().all(|p| *p == Pulse::High) {
-                            Pulse::Low
-                        } else {
-                            Pulse::High
-                        };
+    let mut simulator = Simulator::new(modules);
 
-                        module.t = ModuleType::Conjunction(memory);
+    let max_presses = std::env::args()
+        .skip_while(|arg| arg != "--max-presses")
+        .nth(1)
+        .map(|n| n.parse().expect("--max-presses expects a number"))
+        .unwrap_or(MAX_PRESSES);
 
-                        for dest in module.dests.iter() {
-                            q.push_back((target.clone(), dest.clone(), new_pulse.clone()))
-                        }
-                    }
-                    ModuleType::Broadcaster => continue,
-                }
-            }
+    match find_rx(&mut simulator, max_presses) {
+        Ok(answer) => println!("Answer: {answer}"),
+        Err(error) => {
+            eprintln!("Error: {error}");
+            std::process::exit(1);
         }
     }
 }
 
-fn gcd(a: u64, b: u64) -> u64 {
-    if b == 0 {
-        a
-    } else {
-        gcd(b, a % b)
-    }
-}
-
-fn lcm(a: u64, b: u64) -> u64 {
-    if a == 0 || b == 0 {
-        0
-    } else {
-        (a * b) / gcd(a, b)
-    }
-}
-
-fn parse_module_name(input: &str) -> IResult<&str, ModuleName> {
-    map(alpha1, |s: &str| ModuleName(s.to_owned()))(input)
-}
+#[cfg(test)]
+mod tests {
+    use day_20_rust::{cycles::CycleError, parse_modules, ModuleName, Simulator};
 
-fn parse_module(input: &str) -> IResult<&str, (ModuleName, Module)> {
-    map(
-        separated_pair(
-            alt((
-                map(tag("broadcaster"), |_| {
-                    (
-                        ModuleType::Broadcaster,
-                        ModuleName("broadcaster".to_owned()),
-                    )
-                }),
-                map(
-                    tuple((complete::char('%'), parse_module_name)),
-                    |(_, name)| (ModuleType::flip_flop(), name),
-                ),
-                map(
-                    tuple((complete::char('&'), parse_module_name)),
-                    |(_, name)| (ModuleType::Conjunction(HashMap::new()), name),
-                ),
-            )),
-            tag(" -> "),
-            separated_list1(tag(", "), parse_module_name),
-        ),
-        |((t, name), dests)| (name, Module { t, dests }),
-    )(input)
-}
+    use crate::find_rx;
 
-fn parse_modules(input: &str) -> IResult<&str, Modules> {
-    map(separated_list1(line_ending, parse_module), |modules| {
-        let mut modules = Modules(modules.into_iter().collect());
-        modules.set_conjunctions();
-        modules
-    })(input)
-}
+    fn find(input: &str) -> Result<u64, CycleError> {
+        let (_, modules) = parse_modules(input).expect("failed to parse");
+        find_rx(&mut Simulator::new(modules), 100)
+    }
 
-fn main() {
-    let (_, mut modules) = parse_modules(include_str!("input.txt")).expect("failed to parse");
-    let answer = modules.find_rx();
-    println!("Answer: {answer}");
+    #[test]
+    fn checks_the_feed() {
+        assert_eq!(
+            find("broadcaster -> a"),
+            Err(CycleError::NoInputs(ModuleName::new("rx")))
+        );
+        assert_eq!(
+            find("broadcaster -> a, b\n%a -> rx\n%b -> rx"),
+            Err(CycleError::MultipleFeeders {
+                target: ModuleName::new("rx"),
+                feeders: vec![ModuleName::new("a"), ModuleName::new("b")]
+            })
+        );
+        assert_eq!(
+            find("broadcaster -> a\n%a -> rx"),
+            Err(CycleError::FeedNotConjunction(ModuleName::new("a")))
+        );
+        assert_eq!(
+            find("broadcaster -> a, b\n%a -> c\n%b -> c\n&c -> rx"),
+            Ok(1)
+        );
+    }
 }
@@ -0,0 +1,356 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{ModuleId, ModuleName, Pulse, PulseCounter, Simulator, Watch};
+
+/// How many times every input has to be seen before its period is trusted,
+/// giving three intervals to compare.
+pub const OBSERVATIONS: usize = 4;
+
+/// How many presses to give the inputs to repeat before giving up.
+pub const MAX_PRESSES: u64 = 1_000_000;
+
+/// How many presses to remember the whole network for while waiting for it
+/// to repeat, which is only worth it for small networks.
+pub const MAX_STATES: u64 = 100_000;
+
+/// Something that happens on press `first` and then every `period` presses
+/// after that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    pub first: u64,
+    pub period: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CycleError {
+    NoInputs(ModuleName),
+    /// More than one module sends to the module being solved for.
+    MultipleFeeders {
+        target: ModuleName,
+        feeders: Vec<ModuleName>,
+    },
+    /// The only module sending to the module being solved for is not a
+    /// conjunction, so its inputs lining up says nothing about what it
+    /// sends.
+    FeedNotConjunction(ModuleName),
+    /// An input did not send its pulse often enough within the presses.
+    NotSeen {
+        input: ModuleName,
+        presses: u64,
+    },
+    /// An input sent its pulse on presses that are not evenly spaced.
+    NotPeriodic {
+        input: ModuleName,
+        seen: Vec<u64>,
+    },
+    /// No press lines up with every cycle.
+    NoSolution,
+    /// The whole network did not come back to an earlier state.
+    NoRepeat {
+        presses: u64,
+    },
+}
+
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CycleError::NoInputs(name) => write!(f, "nothing sends pulses to `{}`", name.0),
+            CycleError::MultipleFeeders { target, feeders } => {
+                let feeders = feeders.iter().map(|n| n.0.as_str()).collect::<Vec<_>>();
+                write!(
+                    f,
+                    "`{}` is fed by {}, not a single conjunction",
+                    target.0,
+                    feeders.join(", ")
+                )
+            }
+            CycleError::FeedNotConjunction(name) => {
+                write!(f, "`{}` is not a conjunction", name.0)
+            }
+            CycleError::NotSeen { input, presses } => write!(
+                f,
+                "`{}` did not repeat {OBSERVATIONS} times within {presses} presses",
+                input.0
+            ),
+            CycleError::NotPeriodic { input, seen } => write!(
+                f,
+                "`{}` is not periodic, it was seen on presses {seen:?}",
+                input.0
+            ),
+            CycleError::NoSolution => write!(f, "the cycles never line up"),
+            CycleError::NoRepeat { presses } => {
+                write!(f, "the network did not repeat within {presses} presses")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CycleError {}
+
+impl Cycle {
+    /// The cycle followed by `seen`, if the presses are evenly spaced.
+    pub fn detect(seen: &[u64]) -> Option<Self> {
+        let period = *seen.get(1)? - seen[0];
+        seen.windows(2)
+            .all(|pair| pair[1] - pair[0] == period)
+            .then_some(Cycle {
+                first: seen[0],
+                period,
+            })
+    }
+}
+
+/// Presses the button until every input of `target` has sent it `pulse`
+/// on [`OBSERVATIONS`] different presses, and works out the cycle each of
+/// them follows.
+pub fn find_cycles(
+    simulator: &mut Simulator,
+    target: ModuleId,
+    pulse: Pulse,
+    max_presses: u64,
+) -> Result<Vec<(ModuleId, Cycle)>, CycleError> {
+    let inputs = simulator
+        .modules
+        .inputs(simulator.name(target))
+        .iter()
+        .filter_map(|input| simulator.id(input))
+        .collect::<Vec<_>>();
+    if inputs.is_empty() {
+        return Err(CycleError::NoInputs(simulator.name(target).clone()));
+    }
+
+    let mut watch = Watch::new(target, pulse);
+    let seen_enough =
+        |watch: &Watch, input: ModuleId| watch.seen.get(&input).map_or(0, Vec::len) >= OBSERVATIONS;
+    for _ in 0..max_presses {
+        if inputs.iter().all(|&input| seen_enough(&watch, input)) {
+            break;
+        }
+        simulator.press(&mut watch);
+    }
+
+    inputs
+        .into_iter()
+        .map(|input| {
+            let name = simulator.name(input).clone();
+            if !seen_enough(&watch, input) {
+                return Err(CycleError::NotSeen {
+                    input: name,
+                    presses: max_presses,
+                });
+            }
+            let seen = &watch.seen[&input];
+            match Cycle::detect(seen) {
+                Some(cycle) => Ok((input, cycle)),
+                None => Err(CycleError::NotPeriodic {
+                    input: name,
+                    seen: seen.clone(),
+                }),
+            }
+        })
+        .collect()
+}
+
+/// The network coming back to the state it was in `start` presses in,
+/// every `period` presses, along with the pulses counted up to the repeat.
+#[derive(Debug)]
+pub struct NetworkCycle {
+    pub start: u64,
+    pub period: u64,
+    /// The pulses sent by the first `n` presses, for every `n` up to
+    /// `start + period`.
+    counts: Vec<PulseCounter>,
+}
+
+impl NetworkCycle {
+    /// The pulses sent by the first `presses` presses, without pressing the
+    /// button that many times.
+    pub fn pulses(&self, presses: u64) -> PulseCounter {
+        if presses <= self.start + self.period {
+            return self.counts[presses as usize];
+        }
+
+        let start = self.counts[self.start as usize];
+        let end = self.counts[(self.start + self.period) as usize];
+        let cycles = (presses - self.start) / self.period;
+        let rest = self.counts[(self.start + (presses - self.start) % self.period) as usize];
+        PulseCounter {
+            low: rest.low + cycles * (end.low - start.low),
+            high: rest.high + cycles * (end.high - start.high),
+        }
+    }
+}
+
+/// Presses the button until the whole network repeats a state it was in
+/// earlier, counting presses from where the simulator is now.
+pub fn find_network_cycle(
+    simulator: &mut Simulator,
+    max_presses: u64,
+) -> Result<NetworkCycle, CycleError> {
+    let mut seen = HashMap::from([(simulator.state(), 0)]);
+    let mut counter = PulseCounter::default();
+    let mut counts = vec![counter];
+
+    for presses in 1..=max_presses {
+        simulator.press(&mut counter);
+        counts.push(counter);
+        if let Some(start) = seen.insert(simulator.state(), presses) {
+            return Ok(NetworkCycle {
+                start,
+                period: presses - start,
+                counts,
+            });
+        }
+    }
+
+    Err(CycleError::NoRepeat {
+        presses: max_presses,
+    })
+}
+
+/// Solves `a * x + b * y = gcd(a, b)`, returning the gcd along with `x`
+/// and `y`.
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (gcd, x, y) = extended_gcd(b, a % b);
+        (gcd, y, x - (a / b) * y)
+    }
+}
+
+/// The first press on which every cycle happens at once, combining them
+/// with the Chinese remainder theorem so the periods need not be coprime.
+pub fn first_press(cycles: &[Cycle]) -> Result<u64, CycleError> {
+    let mut combined = (0i128, 1i128);
+    for cycle in cycles {
+        let (residue, modulus) = combined;
+        let (first, period) = (cycle.first as i128, cycle.period as i128);
+
+        let (gcd, inverse, _) = extended_gcd(modulus, period);
+        if (first - residue) % gcd != 0 {
+            return Err(CycleError::NoSolution);
+        }
+        let lcm = modulus / gcd * period;
+        let step = ((first - residue) / gcd * inverse).rem_euclid(period / gcd);
+        combined = ((residue + modulus * step).rem_euclid(lcm), lcm);
+    }
+
+    let (residue, modulus) = combined;
+    let earliest = cycles.iter().map(|cycle| cycle.first).max().unwrap_or(0) as i128;
+    let press = if residue >= earliest {
+        residue
+    } else {
+        residue + (earliest - residue + modulus - 1) / modulus * modulus
+    };
+    Ok(press as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse_modules, ModuleName, Pulse, PulseCounter, Simulator};
+
+    use super::{
+        find_cycles, find_network_cycle, first_press, Cycle, CycleError, MAX_PRESSES, MAX_STATES,
+    };
+
+    fn simulator(input: &str) -> Simulator {
+        let (_, modules) = parse_modules(input).expect("failed to parse");
+        Simulator::new(modules)
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let mut simulator = simulator(include_str!("bin/test-2.txt"));
+        let output = simulator.id(&ModuleName::new("output")).unwrap();
+        let con = simulator.id(&ModuleName::new("con")).unwrap();
+
+        assert_eq!(
+            find_cycles(&mut simulator, output, Pulse::Low, MAX_PRESSES),
+            Ok(vec![(
+                con,
+                Cycle {
+                    first: 1,
+                    period: 2
+                }
+            )])
+        );
+        assert_eq!(
+            find_cycles(&mut simulator, output, Pulse::High, 3),
+            Err(CycleError::NotSeen {
+                input: ModuleName::new("con"),
+                presses: 3
+            })
+        );
+        assert_eq!(Cycle::detect(&[1, 3, 6]), None);
+    }
+
+    #[test]
+    fn combines_cycles() {
+        let cycle = |first, period| Cycle { first, period };
+
+        assert_eq!(first_press(&[cycle(3, 3), cycle(5, 5)]), Ok(15));
+        assert_eq!(first_press(&[cycle(2, 3), cycle(3, 5)]), Ok(8));
+        assert_eq!(first_press(&[cycle(2, 4), cycle(4, 6)]), Ok(10));
+        assert_eq!(first_press(&[cycle(9, 2), cycle(2, 3)]), Ok(11));
+        assert_eq!(
+            first_press(&[cycle(1, 2), cycle(2, 4)]),
+            Err(CycleError::NoSolution)
+        );
+    }
+
+    #[test]
+    fn combines_cycles_like_brute_force() {
+        let cycles = (1..12)
+            .flat_map(|first| (1..12).map(move |period| Cycle { first, period }))
+            .collect::<Vec<_>>();
+
+        for a in cycles.iter() {
+            for b in cycles.iter() {
+                let earliest = a.first.max(b.first);
+                let expected = (earliest..earliest + a.period * b.period)
+                    .find(|press| {
+                        [a, b].iter().all(|cycle| {
+                            *press >= cycle.first && (press - cycle.first) % cycle.period == 0
+                        })
+                    })
+                    .ok_or(CycleError::NoSolution);
+                assert_eq!(first_press(&[*a, *b]), expected, "{a:?} and {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn extrapolates_network_cycles() {
+        for (input, period) in [
+            (include_str!("bin/test-1.txt"), 1),
+            (include_str!("bin/test-2.txt"), 4),
+        ] {
+            let cycle = find_network_cycle(&mut simulator(input), MAX_STATES).unwrap();
+            assert_eq!((cycle.start, cycle.period), (0, period));
+
+            for presses in [0, 3, 1000, 1001] {
+                let mut simulator = simulator(input);
+                let mut counter = PulseCounter::default();
+                for _ in 0..presses {
+                    simulator.press(&mut counter);
+                }
+                assert_eq!(cycle.pulses(presses), counter);
+            }
+        }
+
+        let cycle =
+            find_network_cycle(&mut simulator(include_str!("bin/test-1.txt")), MAX_STATES).unwrap();
+        assert_eq!(
+            cycle.pulses(1_000_000_000_000),
+            PulseCounter {
+                low: 8_000_000_000_000,
+                high: 4_000_000_000_000
+            }
+        );
+        assert_eq!(
+            find_network_cycle(&mut simulator(include_str!("bin/input.txt")), 100).unwrap_err(),
+            CycleError::NoRepeat { presses: 100 }
+        );
+    }
+}
@@ -0,0 +1,765 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    ops::Range,
+};
+
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{self, alpha1, line_ending},
+    combinator::map,
+    multi::separated_list1,
+    sequence::{separated_pair, tuple},
+    IResult,
+};
+use petgraph::{dot::Dot, Graph};
+
+pub mod cycles;
+
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub t: ModuleType,
+    pub dests: Vec<ModuleName>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleType {
+    FlipFlop,
+    Conjunction,
+    Broadcaster,
+    /// A module that is only ever sent to, which records what it receives.
+    Output,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pulse {
+    Low,
+    High,
+}
+
+impl fmt::Display for Pulse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pulse::Low => write!(f, "low"),
+            Pulse::High => write!(f, "high"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct ModuleName(pub String);
+
+impl ModuleName {
+    pub fn new(name: &str) -> Self {
+        Self(name.to_owned())
+    }
+}
+
+#[derive(Debug)]
+pub struct Modules {
+    pub map: ModuleMap,
+    /// Modules that were defined more than once, each time after the first.
+    /// The last definition is the one kept in `map`.
+    pub duplicates: Vec<ModuleName>,
+}
+pub type ModuleMap = HashMap<ModuleName, Module>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// There is no `broadcaster` for the button to send to.
+    MissingBroadcaster,
+    /// A module named `button`, which is where every press starts.
+    ReservedName(ModuleName),
+    Duplicate(ModuleName),
+    /// A destination that is never defined, which becomes an output.
+    UndefinedDestination {
+        from: ModuleName,
+        to: ModuleName,
+    },
+    Unreachable(ModuleName),
+}
+
+impl Diagnostic {
+    /// A network without a broadcaster, or with modules that clash by name,
+    /// cannot be simulated. Undefined destinations and unreachable modules
+    /// are only warnings, since every press still runs.
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            Diagnostic::MissingBroadcaster | Diagnostic::ReservedName(_) | Diagnostic::Duplicate(_)
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::MissingBroadcaster => write!(f, "no `broadcaster` module"),
+            Diagnostic::ReservedName(name) => {
+                write!(f, "`{}` is reserved for the button", name.0)
+            }
+            Diagnostic::Duplicate(name) => write!(f, "`{}` is defined more than once", name.0),
+            Diagnostic::UndefinedDestination { from, to } => write!(
+                f,
+                "`{}` sends to undefined module `{}`, which will be an output",
+                from.0, to.0
+            ),
+            Diagnostic::Unreachable(name) => {
+                write!(f, "`{}` is unreachable from `broadcaster`", name.0)
+            }
+        }
+    }
+}
+
+/// Prints the diagnostics to stderr and returns whether the network has
+/// errors that should stop it from being simulated.
+pub fn report(diagnostics: &[Diagnostic]) -> bool {
+    for diagnostic in diagnostics {
+        let level = if diagnostic.is_error() {
+            "Error"
+        } else {
+            "Warning"
+        };
+        eprintln!("{level}: {diagnostic}");
+    }
+    diagnostics.iter().any(Diagnostic::is_error)
+}
+
+impl Modules {
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let broadcaster = ModuleName::new("broadcaster");
+
+        let mut names = self.map.keys().collect::<Vec<_>>();
+        names.sort();
+
+        if !self.map.contains_key(&broadcaster) {
+            diagnostics.push(Diagnostic::MissingBroadcaster);
+        }
+
+        let button = ModuleName::new("button");
+        if self.map.contains_key(&button) {
+            diagnostics.push(Diagnostic::ReservedName(button));
+        }
+
+        let mut duplicates = self.duplicates.clone();
+        duplicates.sort();
+        duplicates.dedup();
+        diagnostics.extend(duplicates.into_iter().map(Diagnostic::Duplicate));
+
+        for name in names.iter() {
+            for dest in self.map[*name].dests.iter() {
+                if !self.map.contains_key(dest) {
+                    diagnostics.push(Diagnostic::UndefinedDestination {
+                        from: (*name).clone(),
+                        to: dest.clone(),
+                    });
+                }
+            }
+        }
+
+        if self.map.contains_key(&broadcaster) {
+            let mut reachable = HashSet::from([&broadcaster]);
+            let mut queue = vec![&broadcaster];
+            while let Some(name) = queue.pop() {
+                for dest in self.map.get(name).iter().flat_map(|module| &module.dests) {
+                    if reachable.insert(dest) {
+                        queue.push(dest);
+                    }
+                }
+            }
+
+            diagnostics.extend(
+                names
+                    .into_iter()
+                    .filter(|name| !reachable.contains(name))
+                    .map(|name| Diagnostic::Unreachable(name.clone())),
+            );
+        }
+
+        diagnostics
+    }
+
+    /// The modules that send pulses to `name`.
+    pub fn inputs(&self, name: &ModuleName) -> Vec<ModuleName> {
+        let mut inputs = self
+            .map
+            .iter()
+            .filter(|(_, module)| module.dests.contains(name))
+            .map(|(input, _)| input.clone())
+            .collect::<Vec<_>>();
+        inputs.sort();
+        inputs
+    }
+}
+
+/// Modules are numbered when the simulator is built, with the button at 0
+/// and the broadcaster at 1.
+pub type ModuleId = u16;
+
+/// A pulse sent from the first module to the second.
+pub type Event = (ModuleId, ModuleId, Pulse);
+
+/// Gets to see every pulse sent while the button is pressed.
+pub trait Observer {
+    /// Called for every pulse in the order they are delivered, `press`
+    /// counting button presses from 1.
+    fn observe(&mut self, press: u64, event: &Event);
+}
+
+impl<F: FnMut(u64, &Event)> Observer for F {
+    fn observe(&mut self, press: u64, event: &Event) {
+        self(press, event)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    origin: ModuleId,
+    target: ModuleId,
+    /// The bit standing for `origin` in the memory of `target`, if that is
+    /// a conjunction.
+    bit: u64,
+}
+
+/// A snapshot of the whole network between presses.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct State {
+    flip_flops: Vec<u64>,
+    memory: Vec<u64>,
+}
+
+/// Runs pulses through the network one at a time.
+///
+/// Everything is indexed by `ModuleId`: flip-flop states live in a bitset
+/// and every conjunction remembers its inputs as a bitmask of the ones
+/// that last sent a high pulse, so conjunctions can have at most 64 inputs.
+#[derive(Debug)]
+pub struct Simulator {
+    pub modules: Modules,
+    names: Vec<ModuleName>,
+    ids: HashMap<ModuleName, ModuleId>,
+    /// `None` for the button.
+    kinds: Vec<Option<ModuleType>>,
+    edges: Vec<Edge>,
+    /// The edges leaving every module, as a range into `edges`.
+    outputs: Vec<Range<u32>>,
+    flip_flops: Vec<u64>,
+    memory: Vec<u64>,
+    /// The memory of every conjunction once all its inputs sent high.
+    full: Vec<u64>,
+    /// The pulses every output has received.
+    received: Vec<PulseCounter>,
+    /// Edges with a pulse on their way along them.
+    queue: VecDeque<(u32, Pulse)>,
+    presses: u64,
+}
+
+impl Simulator {
+    /// Builds a simulator for the network, with every destination that is
+    /// not defined added to it as an output.
+    pub fn new(mut modules: Modules) -> Self {
+        let button = ModuleName::new("button");
+        let broadcaster = ModuleName::new("broadcaster");
+
+        let mut defined = modules
+            .map
+            .keys()
+            .filter(|name| **name != button && **name != broadcaster)
+            .cloned()
+            .collect::<Vec<_>>();
+        defined.sort();
+        let mut sinks = modules
+            .map
+            .values()
+            .flat_map(|module| module.dests.iter())
+            .filter(|name| !modules.map.contains_key(name) && **name != button)
+            .cloned()
+            .collect::<Vec<_>>();
+        sinks.sort();
+        sinks.dedup();
+        for sink in sinks.iter() {
+            modules.map.insert(
+                sink.clone(),
+                Module {
+                    t: ModuleType::Output,
+                    dests: vec![],
+                },
+            );
+        }
+
+        let names = [button.clone(), broadcaster]
+            .into_iter()
+            .chain(defined)
+            .chain(sinks)
+            .collect::<Vec<_>>();
+        assert!(
+            names.len() <= ModuleId::MAX as usize,
+            "too many modules to number"
+        );
+        let ids = names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| (name.clone(), id as ModuleId))
+            .collect::<HashMap<_, _>>();
+        let kinds = names
+            .iter()
+            .map(|name| {
+                if *name == button {
+                    None
+                } else {
+                    modules.map.get(name).map(|module| module.t)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut full = vec![0u64; names.len()];
+        let mut edges = Vec::new();
+        let mut outputs = Vec::with_capacity(names.len());
+        for (origin, name) in names.iter().enumerate() {
+            let start = edges.len() as u32;
+            let dests = match modules.map.get(name) {
+                _ if *name == button => vec![ModuleName::new("broadcaster")],
+                Some(module) => module.dests.clone(),
+                None => vec![],
+            };
+            for dest in dests {
+                let target = ids[&dest];
+                let mut bit = 0;
+                if kinds[target as usize] == Some(ModuleType::Conjunction) {
+                    let inputs = &mut full[target as usize];
+                    bit = 1u64
+                        .checked_shl(inputs.count_ones())
+                        .unwrap_or_else(|| panic!("`{}` has more than 64 inputs", dest.0));
+                    *inputs |= bit;
+                }
+                edges.push(Edge {
+                    origin: origin as ModuleId,
+                    target,
+                    bit,
+                });
+            }
+            outputs.push(start..edges.len() as u32);
+        }
+
+        Self {
+            modules,
+            flip_flops: vec![0; names.len().div_ceil(64)],
+            memory: vec![0; names.len()],
+            received: vec![PulseCounter::default(); names.len()],
+            names,
+            ids,
+            kinds,
+            edges,
+            outputs,
+            full,
+            queue: VecDeque::new(),
+            presses: 0,
+        }
+    }
+
+    /// How many times the button has been pressed.
+    pub fn presses(&self) -> u64 {
+        self.presses
+    }
+
+    /// Every flip-flop and conjunction memory, which together decide what
+    /// the next press will do.
+    pub fn state(&self) -> State {
+        State {
+            flip_flops: self.flip_flops.clone(),
+            memory: self
+                .memory
+                .iter()
+                .zip(self.full.iter())
+                .filter(|(_, full)| **full != 0)
+                .map(|(memory, _)| *memory)
+                .collect(),
+        }
+    }
+
+    pub fn id(&self, name: &ModuleName) -> Option<ModuleId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name(&self, id: ModuleId) -> &ModuleName {
+        &self.names[id as usize]
+    }
+
+    /// The name of every module, indexed by id.
+    pub fn names(&self) -> &[ModuleName] {
+        &self.names
+    }
+
+    /// The pulses `id` has received so far, if it is an output.
+    pub fn received(&self, id: ModuleId) -> Option<PulseCounter> {
+        (self.kinds[id as usize] == Some(ModuleType::Output)).then_some(self.received[id as usize])
+    }
+
+    /// The network in DOT format, with a box for every flip-flop, an ellipse
+    /// for every conjunction and a diamond for the broadcaster. With `state`,
+    /// flip-flops that are on are red, and so are conjunctions that remember
+    /// a high pulse from every input, or orange from only some of them.
+    pub fn to_dot(&self, state: bool) -> String {
+        let mut graph = Graph::<String, ()>::new();
+        for name in self.names.iter() {
+            graph.add_node(name.0.clone());
+        }
+        graph.extend_with_edges(
+            self.edges
+                .iter()
+                .map(|edge| (edge.origin as u32, edge.target as u32)),
+        );
+
+        format!(
+            "{:?}",
+            Dot::with_attr_getters(
+                &graph,
+                &[petgraph::dot::Config::EdgeNoLabel],
+                &|_, _| String::new(),
+                &|_, (idx, _)| {
+                    let index = idx.index();
+                    let shape = match self.kinds[index] {
+                        Some(ModuleType::FlipFlop) => "shape = box",
+                        Some(ModuleType::Conjunction) => "shape = ellipse",
+                        Some(ModuleType::Broadcaster) => "shape = diamond",
+                        Some(ModuleType::Output) => "shape = doublecircle",
+                        None => "shape = plaintext",
+                    };
+                    let colour = match self.kinds[index] {
+                        _ if !state => "",
+                        Some(ModuleType::FlipFlop)
+                            if self.flip_flops[index / 64] & (1 << (index % 64)) != 0 =>
+                        {
+                            " color = red"
+                        }
+                        Some(ModuleType::Conjunction) if self.memory[index] == 0 => "",
+                        Some(ModuleType::Conjunction) if self.memory[index] == self.full[index] => {
+                            " color = red"
+                        }
+                        Some(ModuleType::Conjunction) => " color = orange",
+                        _ => "",
+                    };
+                    format!("{shape}{colour}")
+                }
+            )
+        )
+    }
+
+    /// Delivers the next pulse in the queue and returns it, or `None` once
+    /// the network has settled.
+    pub fn step(&mut self) -> Option<Event> {
+        let (edge, pulse) = self.queue.pop_front()?;
+        let Edge {
+            origin,
+            target,
+            bit,
+        } = self.edges[edge as usize];
+        let index = target as usize;
+
+        let sent = match self.kinds[index] {
+            Some(ModuleType::FlipFlop) => match pulse {
+                Pulse::Low => {
+                    let word = &mut self.flip_flops[index / 64];
+                    *word ^= 1 << (index % 64);
+                    if *word & (1 << (index % 64)) != 0 {
+                        Some(Pulse::High)
+                    } else {
+                        Some(Pulse::Low)
+                    }
+                }
+                Pulse::High => None,
+            },
+            Some(ModuleType::Conjunction) => {
+                match pulse {
+                    Pulse::Low => self.memory[index] &= !bit,
+                    Pulse::High => self.memory[index] |= bit,
+                }
+                if self.memory[index] == self.full[index] {
+                    Some(Pulse::Low)
+                } else {
+                    Some(Pulse::High)
+                }
+            }
+            Some(ModuleType::Broadcaster) => Some(pulse),
+            Some(ModuleType::Output) => {
+                self.received[index].observe(self.presses, &(origin, target, pulse));
+                None
+            }
+            None => None,
+        };
+
+        if let Some(sent) = sent {
+            for edge in self.outputs[index].clone() {
+                self.queue.push_back((edge, sent));
+            }
+        }
+
+        Some((origin, target, pulse))
+    }
+
+    /// Pushes the button and runs the network until it settles, showing
+    /// every pulse to `observer`, starting with the one from the button to
+    /// the broadcaster.
+    pub fn press(&mut self, observer: &mut impl Observer) {
+        self.presses += 1;
+        for edge in self.outputs[0].clone() {
+            self.queue.push_back((edge, Pulse::Low));
+        }
+
+        while let Some(event) = self.step() {
+            observer.observe(self.presses, &event);
+        }
+    }
+}
+
+/// Counts low and high pulses.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PulseCounter {
+    pub low: u64,
+    pub high: u64,
+}
+
+impl Observer for PulseCounter {
+    fn observe(&mut self, _: u64, (_, _, pulse): &Event) {
+        match pulse {
+            Pulse::Low => self.low += 1,
+            Pulse::High => self.high += 1,
+        }
+    }
+}
+
+/// Records the presses on which each input of a module sent it a given
+/// pulse.
+#[derive(Debug)]
+pub struct Watch {
+    pub target: ModuleId,
+    pub pulse: Pulse,
+    pub seen: HashMap<ModuleId, Vec<u64>>,
+}
+
+impl Watch {
+    pub fn new(target: ModuleId, pulse: Pulse) -> Self {
+        Self {
+            target,
+            pulse,
+            seen: HashMap::new(),
+        }
+    }
+}
+
+impl Observer for Watch {
+    fn observe(&mut self, press: u64, (origin, target, pulse): &Event) {
+        if *target == self.target && *pulse == self.pulse {
+            let presses = self.seen.entry(*origin).or_default();
+            if presses.last() != Some(&press) {
+                presses.push(press);
+            }
+        }
+    }
+}
+
+fn parse_module_name(input: &str) -> IResult<&str, ModuleName> {
+    map(alpha1, |s: &str| ModuleName(s.to_owned()))(input)
+}
+
+fn parse_module(input: &str) -> IResult<&str, (ModuleName, Module)> {
+    map(
+        separated_pair(
+            alt((
+                map(tag("broadcaster"), |_| {
+                    (
+                        ModuleType::Broadcaster,
+                        ModuleName("broadcaster".to_owned()),
+                    )
+                }),
+                map(
+                    tuple((complete::char('%'), parse_module_name)),
+                    |(_, name)| (ModuleType::FlipFlop, name),
+                ),
+                map(
+                    tuple((complete::char('&'), parse_module_name)),
+                    |(_, name)| (ModuleType::Conjunction, name),
+                ),
+            )),
+            tag(" -> "),
+            separated_list1(tag(", "), parse_module_name),
+        ),
+        |((t, name), dests)| (name, Module { t, dests }),
+    )(input)
+}
+
+pub fn parse_modules(input: &str) -> IResult<&str, Modules> {
+    map(separated_list1(line_ending, parse_module), |definitions| {
+        let mut modules = Modules {
+            map: HashMap::new(),
+            duplicates: vec![],
+        };
+        for (name, module) in definitions {
+            if modules.map.insert(name.clone(), module).is_some() {
+                modules.duplicates.push(name);
+            }
+        }
+        modules
+    })(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parse_modules, Diagnostic, Event, ModuleName, Observer, Pulse, PulseCounter, Simulator,
+        Watch,
+    };
+
+    fn simulator(input: &str) -> Simulator {
+        let (_, modules) = parse_modules(input).expect("failed to parse");
+        Simulator::new(modules)
+    }
+
+    #[test]
+    fn events() {
+        let mut simulator = simulator(include_str!("bin/test-1.txt"));
+        let mut events = Vec::new();
+        let names = simulator.names().to_vec();
+        simulator.press(&mut |_, &(origin, target, pulse): &Event| {
+            events.push(format!(
+                "{} -{pulse}-> {}",
+                names[origin as usize].0, names[target as usize].0
+            ))
+        });
+
+        assert_eq!(
+            events,
+            vec![
+                "button -low-> broadcaster",
+                "broadcaster -low-> a",
+                "broadcaster -low-> b",
+                "broadcaster -low-> c",
+                "a -high-> b",
+                "b -high-> c",
+                "c -high-> inv",
+                "inv -low-> a",
+                "a -low-> b",
+                "b -low-> c",
+                "c -low-> inv",
+                "inv -high-> a",
+            ]
+        );
+        assert_eq!(simulator.presses(), 1);
+    }
+
+    #[test]
+    fn counts_pulses() {
+        for (input, low, high) in [
+            (include_str!("bin/test-1.txt"), 8000, 4000),
+            (include_str!("bin/test-2.txt"), 4250, 2750),
+        ] {
+            let mut simulator = simulator(input);
+            let mut counter = PulseCounter::default();
+            for _ in 0..1000 {
+                simulator.press(&mut counter);
+            }
+            assert_eq!(counter, PulseCounter { low, high });
+        }
+    }
+
+    #[test]
+    fn watches_modules() {
+        let mut simulator = simulator(include_str!("bin/test-2.txt"));
+        let output = simulator.id(&ModuleName::new("output")).unwrap();
+        let mut watch = Watch::new(output, Pulse::Low);
+        for _ in 0..4 {
+            simulator.press(&mut watch);
+        }
+
+        let con = simulator.id(&ModuleName::new("con")).unwrap();
+        assert_eq!(watch.seen[&con], vec![1, 3]);
+    }
+
+    #[test]
+    fn exports_dot() {
+        let mut simulator = simulator(include_str!("bin/test-2.txt"));
+        simulator.press(&mut PulseCounter::default());
+
+        let dot = simulator.to_dot(false);
+        assert!(dot.contains(r#"[ label = "\"broadcaster\"" shape = diamond]"#));
+        assert!(dot.contains(r#"[ label = "\"con\"" shape = ellipse]"#));
+        assert!(dot.contains(r#"[ label = "\"output\"" shape = doublecircle]"#));
+        assert!(!dot.contains("color"));
+        assert_eq!(dot.matches(" -> ").count(), 7);
+
+        let dot = simulator.to_dot(true);
+        assert!(dot.contains(r#"[ label = "\"a\"" shape = box color = red]"#));
+    }
+
+    #[test]
+    fn validation() {
+        let (_, modules) = parse_modules(include_str!("bin/test-2.txt")).expect("failed to parse");
+        assert_eq!(
+            modules.validate(),
+            vec![Diagnostic::UndefinedDestination {
+                from: ModuleName::new("con"),
+                to: ModuleName::new("output")
+            }]
+        );
+
+        let (_, modules) =
+            parse_modules("%a -> b\n&b -> a\n%a -> rx\n%c -> a").expect("failed to parse");
+        assert_eq!(
+            modules.validate(),
+            vec![
+                Diagnostic::MissingBroadcaster,
+                Diagnostic::Duplicate(ModuleName::new("a")),
+                Diagnostic::UndefinedDestination {
+                    from: ModuleName::new("a"),
+                    to: ModuleName::new("rx")
+                },
+            ]
+        );
+
+        let (_, modules) =
+            parse_modules("broadcaster -> a\n%a -> b\n&b -> a\n%c -> a").expect("failed to parse");
+        assert_eq!(
+            modules.validate(),
+            vec![Diagnostic::Unreachable(ModuleName::new("c"))]
+        );
+
+        let (_, modules) =
+            parse_modules("broadcaster -> a\n%a -> button\n%button -> a").expect("failed to parse");
+        assert_eq!(
+            modules.validate(),
+            vec![Diagnostic::ReservedName(ModuleName::new("button"))]
+        );
+        let mut simulator = Simulator::new(modules);
+        let mut counter = PulseCounter::default();
+        simulator.press(&mut counter);
+        assert_eq!(counter, PulseCounter { low: 2, high: 1 });
+
+        let (_, modules) = parse_modules("%a -> b\n&b -> a").expect("failed to parse");
+        let mut simulator = Simulator::new(modules);
+        let mut counter = PulseCounter::default();
+        simulator.press(&mut counter);
+        assert_eq!(counter, PulseCounter { low: 1, high: 0 });
+    }
+
+    #[test]
+    fn outputs_record_pulses() {
+        let mut simulator = simulator(include_str!("bin/test-2.txt"));
+        let output = simulator.id(&ModuleName::new("output")).unwrap();
+        let con = simulator.id(&ModuleName::new("con")).unwrap();
+
+        let mut received = PulseCounter::default();
+        for _ in 0..1000 {
+            simulator.press(&mut |_, event: &Event| {
+                if event.1 == output {
+                    received.observe(0, event);
+                }
+            });
+        }
+
+        assert_eq!(simulator.received(output), Some(received));
+        assert_eq!(simulator.received(con), None);
+        assert_eq!(received.low + received.high, 1500);
+    }
+}